@@ -5,19 +5,22 @@ extern crate log;
 use anyhow::*;
 use chrono::{DateTime, Utc};
 use clap::Clap;
+use ddpanel::influx::sender_schema::SenderSchema;
 use serde::Deserialize;
+use std::path::PathBuf;
 use tokio::{
     fs::File,
     io::{AsyncBufRead, BufReader, BufWriter},
 };
 
-mod danmu;
 mod export_danmu;
+mod login;
 mod real_popularity;
+mod rewrite_schema;
 mod prelude {
-    pub use crate::danmu::DanmuMsg;
     pub use crate::RawLine;
     pub use anyhow::*;
+    pub use ddpanel::influx::messages::DanmuMsg;
     pub use tokio::{
         fs::File,
         io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt, BufReader, BufWriter},
@@ -30,12 +33,16 @@ enum Action {
     ExportDanmu,
     #[clap(about = "五分钟同接")]
     Popularity,
+    #[clap(about = "扫码登录，保存爬虫使用的 cookie")]
+    Login,
+    #[clap(about = "按 --sender-schema 把录制的文件重新写为 line protocol")]
+    RewriteSchema,
 }
 
 #[derive(Debug, clap::Clap)]
 struct Options {
     #[clap(long = "input", short = 'i', about = "input file")]
-    input: String,
+    input: Option<String>,
 
     #[clap(
         long = "output",
@@ -46,7 +53,21 @@ struct Options {
     output: String,
 
     #[clap(long = "room", short = 'r', about = "room_id")]
-    room: u64,
+    room: Option<u64>,
+
+    #[clap(
+        long = "sender-schema",
+        default_value = "field",
+        about = "sender schema for rewrite-schema: tag, field, hash, bucket[:N] or drop"
+    )]
+    sender_schema: SenderSchema,
+
+    #[clap(
+        long = "cookie",
+        default_value = "cookies.json",
+        about = "cookies for bilibili client"
+    )]
+    cookie: PathBuf,
 
     #[clap(subcommand)]
     action: Action,
@@ -67,15 +88,22 @@ async fn main() -> Result<()> {
     }
 
     let args = Options::parse();
+    if let Action::Login = args.action {
+        return login::run(&args.cookie).await;
+    }
+    let input = args.input.context("--input is required")?;
+    if let Action::RewriteSchema = args.action {
+        return rewrite_schema::run(input, &args.output, args.sender_schema).await;
+    }
 
-    info!("replaying file {:?}", args.input);
-    let f = File::open(&args.input).await?;
+    info!("replaying file {:?}", input);
+    let f = File::open(&input).await?;
     let f = BufReader::new(f);
 
     let writer = File::create(args.output).await?;
     let writer = BufWriter::new(writer);
 
-    let reader: Box<dyn AsyncBufRead + Unpin> = if args.input.ends_with("gz") {
+    let reader: Box<dyn AsyncBufRead + Unpin> = if input.ends_with("gz") {
         info!("gz detected, treating as gz");
         let reader = async_compression::tokio::bufread::GzipDecoder::new(f);
         Box::new(BufReader::new(reader))
@@ -84,11 +112,14 @@ async fn main() -> Result<()> {
     };
     match args.action {
         Action::ExportDanmu => {
-            export_danmu::run(reader, writer, args.room).await?;
+            let room = args.room.context("--room is required")?;
+            export_danmu::run(reader, writer, room).await?;
         }
         Action::Popularity => {
-            real_popularity::run(reader, writer, args.room).await?;
+            let room = args.room.context("--room is required")?;
+            real_popularity::run(reader, writer, room).await?;
         }
+        Action::Login | Action::RewriteSchema => unreachable!(),
     }
 
     Ok(())
@@ -0,0 +1,54 @@
+//! 扫码登录，保存 cookie 给 ddpanel 的爬虫使用
+use crate::prelude::*;
+use cookie_store::CookieStore;
+use ddpanel::{
+    http::{default_builder, BiliHttp, HttpConfig},
+    requests::{QrCodeGenerate, QrCodePoll},
+    spider::cookies,
+};
+use qrcode::{render::unicode::Dense1x2, QrCode};
+use reqwest_cookie_store::CookieStoreMutex;
+use std::{path::Path, sync::Arc, time::Duration};
+
+/// 轮询扫码结果的间隔
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+pub async fn run(cookie_path: &Path) -> Result<()> {
+    let cookies = Arc::new(CookieStoreMutex::new(CookieStore::default()));
+    let http = BiliHttp::new(HttpConfig::default())?
+        .with_clients(|| default_builder().cookie_provider(Arc::clone(&cookies)))?;
+
+    let qr = QrCodeGenerate::request(&http).await?;
+    let image = QrCode::new(qr.url.as_bytes())?
+        .render::<Dense1x2>()
+        .dark_color(Dense1x2::Light)
+        .light_color(Dense1x2::Dark)
+        .build();
+    println!("{}", image);
+    println!("请使用哔哩哔哩手机客户端扫码登录");
+
+    let mut scanned = false;
+    let refresh_token = loop {
+        tokio::time::sleep(POLL_INTERVAL).await;
+        let poll = QrCodePoll::request(&http, &qr.qrcode_key).await?;
+        match poll.code {
+            0 => break poll.refresh_token,
+            86101 => {}
+            86090 => {
+                if !scanned {
+                    println!("已扫码，请在手机上确认");
+                    scanned = true;
+                }
+            }
+            86038 => bail!("二维码已失效，请重新运行"),
+            code => bail!("登录失败 {}: {}", code, poll.message),
+        }
+    };
+
+    let store = cookies
+        .lock()
+        .map_err(|_| anyhow!("cookie store poisoned"))?;
+    cookies::save(cookie_path, &store, Some(&refresh_token))?;
+    info!("登录成功，cookie 已保存到 {}", cookie_path.display());
+    Ok(())
+}
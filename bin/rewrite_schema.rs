@@ -0,0 +1,25 @@
+//! 按新的 sender schema 重新写出录制的文件
+//!
+//! 输入为 ddpanel 录制的 packet 文件（`recorded-*.json.gz`），和 `ddpanel --replay` 一样重放，
+//! 数据点按 schema 写入 gzip 的 line protocol 文件，可以直接用 `influx write` 写入新的 bucket。
+
+use crate::prelude::*;
+use ddpanel::{
+    influx::{messages::set_sender_schema, sender_schema::SenderSchema, InfluxSink},
+    manager::Manager,
+};
+
+/// 重放时的 buffer 大小，和 `ddpanel --replay` 相同
+const BUFFER_SIZE: usize = 128;
+
+pub async fn run(input: String, output: &str, schema: SenderSchema) -> Result<()> {
+    set_sender_schema(schema);
+    info!(
+        "rewriting {} to {} with sender schema {:?}",
+        input, output, schema
+    );
+    Manager::new()
+        .influx_appender(InfluxSink::file(output)?, None, BUFFER_SIZE, false)
+        .replay(input, 0)
+        .await
+}
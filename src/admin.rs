@@ -0,0 +1,194 @@
+//! 运行时管理监控任务的 http 接口
+//!
+//! - `GET /rooms`：列出所有房间及连接状态、爬虫的用户
+//! - `POST /rooms/{id}` / `DELETE /rooms/{id}`：增加 / 删除直播间
+//! - `POST /users/{id}` / `DELETE /users/{id}`：增加 / 删除爬虫用户
+//! - `POST /rooms/{id}/reconnect`：强制重连
+//! - `POST /spider/run`：立刻跑一轮爬虫
+//!
+//! 对任务的修改会交给 [`TaskFactory`](crate::task_factory::TaskFactory) 写回任务文件。
+
+use std::{convert::Infallible, net::SocketAddr};
+
+use anyhow::{anyhow, Context, Result};
+use hyper::{
+    header::{AUTHORIZATION, CONTENT_TYPE},
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use serde::Serialize;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::{
+    monitor::MonitorStatus,
+    task_factory::{TaskChange, TaskCommand},
+};
+
+/// 需要 manager 处理的命令
+#[derive(Debug)]
+pub enum AdminCommand {
+    Status(oneshot::Sender<Status>),
+    /// 房间不存在时回复 false
+    Reconnect(u64, oneshot::Sender<bool>),
+    /// 立刻跑一轮爬虫，正在跑的任务跑完这一轮后再跑一轮
+    RunSpider,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RoomStatus {
+    pub room_id: u64,
+    #[serde(flatten)]
+    pub status: MonitorStatus,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Status {
+    pub rooms: Vec<RoomStatus>,
+    pub users: Vec<u64>,
+}
+
+#[derive(Clone)]
+pub struct AdminServer {
+    manager: mpsc::Sender<AdminCommand>,
+    tasks: mpsc::Sender<TaskCommand>,
+    /// 设置了 DDPANEL_ADMIN_TOKEN 时要求 `Authorization: Bearer <token>`
+    token: Option<String>,
+}
+
+impl AdminServer {
+    pub fn new(manager: mpsc::Sender<AdminCommand>, tasks: mpsc::Sender<TaskCommand>) -> Self {
+        let token = std::env::var("DDPANEL_ADMIN_TOKEN").ok();
+        if token.is_none() {
+            warn!("DDPANEL_ADMIN_TOKEN not set, admin api is not protected.");
+        }
+        Self {
+            manager,
+            tasks,
+            token,
+        }
+    }
+
+    pub async fn serve(self, addr: SocketAddr) -> Result<()> {
+        let make_svc = make_service_fn(move |_conn| {
+            let server = self.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req| {
+                    let server = server.clone();
+                    async move { Ok::<_, Infallible>(server.handle(req).await) }
+                }))
+            }
+        });
+        let server = Server::try_bind(&addr)
+            .context("failed to bind admin api")?
+            .serve(make_svc);
+        info!("admin api listening on {}", addr);
+        server.await?;
+        Ok(())
+    }
+
+    async fn handle(&self, req: Request<Body>) -> Response<Body> {
+        if !self.authorized(&req) {
+            return error_response(StatusCode::UNAUTHORIZED, "unauthorized");
+        }
+        info!("admin api: {} {}", req.method(), req.uri().path());
+        let path: Vec<&str> = req.uri().path().trim_matches('/').split('/').collect();
+        let ret = match (req.method(), path.as_slice()) {
+            (&Method::GET, ["rooms"]) => self.status().await.map(|s| json_response(&s)),
+            (&Method::POST, ["rooms", id]) => self.change(id, TaskChange::AddRoom).await,
+            (&Method::DELETE, ["rooms", id]) => self.change(id, TaskChange::RemoveRoom).await,
+            (&Method::POST, ["users", id]) => self.change(id, TaskChange::AddUser).await,
+            (&Method::DELETE, ["users", id]) => self.change(id, TaskChange::RemoveUser).await,
+            (&Method::POST, ["rooms", id, "reconnect"]) => self.reconnect(id).await,
+            (&Method::POST, ["spider", "run"]) => self.run_spider().await,
+            _ => return error_response(StatusCode::NOT_FOUND, "not found"),
+        };
+        ret.unwrap_or_else(|e| {
+            warn!("admin api error: {:?}", e);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string())
+        })
+    }
+
+    fn authorized(&self, req: &Request<Body>) -> bool {
+        let token = match &self.token {
+            Some(token) => token,
+            None => return true,
+        };
+        req.headers()
+            .get(AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .map(|v| v == token)
+            .unwrap_or(false)
+    }
+
+    async fn status(&self) -> Result<Status> {
+        let (tx, rx) = oneshot::channel();
+        self.manager
+            .send(AdminCommand::Status(tx))
+            .await
+            .map_err(|_| anyhow!("manager stopped"))?;
+        Ok(rx.await?)
+    }
+
+    async fn run_spider(&self) -> Result<Response<Body>> {
+        self.manager
+            .send(AdminCommand::RunSpider)
+            .await
+            .map_err(|_| anyhow!("manager stopped"))?;
+        Ok(ok_response())
+    }
+
+    async fn change(&self, id: &str, change: fn(u64) -> TaskChange) -> Result<Response<Body>> {
+        let id = match id.parse() {
+            Ok(id) => id,
+            Err(_) => return Ok(error_response(StatusCode::BAD_REQUEST, "invalid id")),
+        };
+        let (tx, rx) = oneshot::channel();
+        self.tasks
+            .send((change(id), tx))
+            .await
+            .map_err(|_| anyhow!("task factory stopped"))?;
+        match rx.await? {
+            Ok(()) => Ok(ok_response()),
+            // watch.toml 无效，文件保持不变
+            Err(e) => Ok(error_response(StatusCode::CONFLICT, &format!("{:#}", e))),
+        }
+    }
+
+    async fn reconnect(&self, id: &str) -> Result<Response<Body>> {
+        let id = match id.parse() {
+            Ok(id) => id,
+            Err(_) => return Ok(error_response(StatusCode::BAD_REQUEST, "invalid id")),
+        };
+        let (tx, rx) = oneshot::channel();
+        self.manager
+            .send(AdminCommand::Reconnect(id, tx))
+            .await
+            .map_err(|_| anyhow!("manager stopped"))?;
+        if rx.await? {
+            Ok(ok_response())
+        } else {
+            Ok(error_response(StatusCode::NOT_FOUND, "room not monitored"))
+        }
+    }
+}
+
+fn json_response<T: Serialize>(value: &T) -> Response<Body> {
+    match serde_json::to_string(value) {
+        Ok(s) => Response::builder()
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(s))
+            .unwrap(),
+        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()),
+    }
+}
+
+fn ok_response() -> Response<Body> {
+    json_response(&serde_json::json!({ "ok": true }))
+}
+
+fn error_response(code: StatusCode, msg: &str) -> Response<Body> {
+    let mut resp = json_response(&serde_json::json!({ "ok": false, "error": msg }));
+    *resp.status_mut() = code;
+    resp
+}
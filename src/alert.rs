@@ -0,0 +1,696 @@
+//! 直播事件的 webhook 提醒
+//!
+//! 和 [`InfluxAppender`](crate::influx::InfluxAppender) 一样订阅 packet 通道，
+//! 按 watch.toml 里的 `[[alert]]` 规则匹配开播、大额 SC / 礼物、上舰、弹幕激增和关键词，
+//! 把模板渲染成 JSON 后 POST 到 webhook。
+//!
+//! 只在开播时连接的直播间收不到 `LIVE`，开播提醒由 [`LiveStatusPoller`](crate::live_status::LiveStatusPoller) 检查到开播时通知。
+//!
+//! SC、礼物和上舰按事件本身去重，重复收到的同一事件只提醒一次，不同的事件都会提醒；
+//! 开播、弹幕激增和关键词没有办法区分，同一规则、同一直播间在冷却时间内只提醒一次。
+
+use std::{
+    collections::{HashMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+use biliapi::ws_protocol::{KnownOperation, Operation, Packet};
+use reqwest::{header::CONTENT_TYPE, Client as HttpClient};
+use tokio::sync::{
+    broadcast::{self, error::RecvError},
+    oneshot, watch,
+};
+
+use crate::{
+    influx::{messages::*, GuardMerger, RoomInfo},
+    keyword::{KeywordMatcher, KeywordRule},
+};
+
+/// 弹幕速率的统计窗口（秒）
+const DANMU_WINDOW_SECS: i64 = 60;
+/// webhook 超时
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+/// 同一事件多久内重复收到（重连、回放）不再提醒
+const DEDUPE_WINDOW: Duration = Duration::from_secs(10 * 60);
+
+/// watch.toml 里的一条 `[[alert]]`
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct AlertRule {
+    #[serde(flatten)]
+    pub kind: AlertKind,
+    /// POST 的地址
+    pub webhook: String,
+    /// JSON 模板，`{streamer}` 等占位符会被替换（已转义，可以直接放在字符串里）
+    pub body: String,
+    /// 只对这些直播间生效，为空时对所有直播间，可以写短号
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rooms: Vec<u64>,
+    /// 开播、弹幕激增和关键词在同一直播间重复提醒的间隔（秒），SC、礼物和上舰按事件去重
+    #[serde(default = "default_cooldown")]
+    pub cooldown: u64,
+}
+
+fn default_cooldown() -> u64 {
+    5 * 60
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum AlertKind {
+    /// 开播
+    Live,
+    /// 单条 SC 不低于多少元
+    SuperChat { min_price: f64 },
+    /// 单次礼物不低于多少元
+    Gift { min_price: f64 },
+    /// 上舰，等级不低于（数字不大于）max_level，1 总督，2 提督，3 舰长
+    Guard { max_level: u8 },
+    /// 一分钟内弹幕数不低于多少
+    DanmuRate { per_minute: u64 },
+    /// 弹幕命中 `[[keyword]]`，不写 keyword 时为任意关键词
+    Keyword {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        keyword: Option<String>,
+    },
+}
+
+impl AlertRule {
+    /// room_id 为长号，watch_id 为 watch.toml 里写的房间号
+    fn applies_to(&self, room_id: u64, watch_id: Option<u64>) -> bool {
+        self.rooms.is_empty()
+            || self.rooms.contains(&room_id)
+            || watch_id.map(|id| self.rooms.contains(&id)).unwrap_or(false)
+    }
+}
+
+impl AlertKind {
+    fn name(&self) -> &'static str {
+        match self {
+            AlertKind::Live => "live",
+            AlertKind::SuperChat { .. } => "super_chat",
+            AlertKind::Gift { .. } => "gift",
+            AlertKind::Guard { .. } => "guard",
+            AlertKind::DanmuRate { .. } => "danmu_rate",
+            AlertKind::Keyword { .. } => "keyword",
+        }
+    }
+}
+
+/// 一个直播事件，用来匹配规则和渲染模板
+#[derive(Debug)]
+enum Event {
+    Live,
+    SuperChat {
+        id: u64,
+        user: String,
+        price: f64,
+        message: String,
+    },
+    Gift {
+        uid: u64,
+        /// 送出的时间（unix 秒）
+        timestamp: i64,
+        /// SEND_GIFT 的 tid，同一秒内的礼物靠它区分
+        tid: u64,
+        user: String,
+        gift: String,
+        price: f64,
+    },
+    Guard {
+        uid: u64,
+        /// 合并后第一条消息的时间（unix 秒）
+        timestamp: i64,
+        user: String,
+        gift: String,
+        level: u8,
+        price: f64,
+    },
+    DanmuRate {
+        per_minute: u64,
+    },
+    Keyword {
+        keyword: String,
+        user: String,
+        message: String,
+    },
+}
+
+impl Event {
+    /// 能区分的事件的标识，同一事件只提醒一次。其他事件为 None，按冷却时间限速
+    fn identity(&self) -> Option<String> {
+        match self {
+            Event::SuperChat { id, .. } if *id != 0 => Some(format!("sc:{}", id)),
+            Event::Gift {
+                uid,
+                timestamp,
+                tid,
+                gift,
+                ..
+            } => Some(format!("gift:{}:{}:{}:{}", uid, gift, timestamp, tid)),
+            Event::Guard {
+                uid,
+                timestamp,
+                level,
+                ..
+            } => Some(format!("guard:{}:{}:{}", uid, level, timestamp)),
+            _ => None,
+        }
+    }
+
+    fn matches(&self, kind: &AlertKind) -> bool {
+        match (self, kind) {
+            (Event::Live, AlertKind::Live) => true,
+            (Event::SuperChat { price, .. }, AlertKind::SuperChat { min_price }) => {
+                price >= min_price
+            }
+            (Event::Gift { price, .. }, AlertKind::Gift { min_price }) => price >= min_price,
+            (Event::Guard { level, .. }, AlertKind::Guard { max_level }) => {
+                *level > 0 && level <= max_level
+            }
+            (Event::DanmuRate { per_minute: rate }, AlertKind::DanmuRate { per_minute }) => {
+                rate >= per_minute
+            }
+            (Event::Keyword { keyword, .. }, AlertKind::Keyword { keyword: expected }) => {
+                expected.as_ref().map(|k| k == keyword).unwrap_or(true)
+            }
+            _ => false,
+        }
+    }
+
+    /// 模板里可用的变量
+    fn vars(&self) -> Vec<(&'static str, String)> {
+        match self {
+            Event::Live => vec![],
+            Event::SuperChat {
+                user,
+                price,
+                message,
+                ..
+            } => vec![
+                ("user", user.clone()),
+                ("price", price.to_string()),
+                ("message", message.clone()),
+            ],
+            Event::Gift {
+                user, gift, price, ..
+            } => vec![
+                ("user", user.clone()),
+                ("gift", gift.clone()),
+                ("price", price.to_string()),
+            ],
+            Event::Guard {
+                user,
+                gift,
+                level,
+                price,
+                ..
+            } => vec![
+                ("user", user.clone()),
+                ("gift", gift.clone()),
+                ("level", level.to_string()),
+                ("price", price.to_string()),
+            ],
+            Event::DanmuRate { per_minute } => vec![("rate", per_minute.to_string())],
+            Event::Keyword {
+                keyword,
+                user,
+                message,
+            } => vec![
+                ("keyword", keyword.clone()),
+                ("user", user.clone()),
+                ("message", message.clone()),
+            ],
+        }
+    }
+}
+
+pub struct Alerter {
+    rules: watch::Receiver<Vec<AlertRule>>,
+    keyword_rules: watch::Receiver<Vec<KeywordRule>>,
+    keywords: KeywordMatcher,
+    packets: broadcast::Receiver<Packet>,
+    /// 只在开播时连接时，轮询检查到开播的直播间
+    went_live: broadcast::Receiver<u64>,
+    client: HttpClient,
+    /// (规则序号, 直播间) => 上次提醒的时间，用于没有标识的事件
+    last_sent: HashMap<(usize, u64), Instant>,
+    /// (规则序号, 事件标识) => 提醒的时间
+    sent_events: HashMap<(usize, String), Instant>,
+    /// 直播间 => 每秒的弹幕数
+    danmu: HashMap<u64, VecDeque<(i64, u64)>>,
+    /// 合并同一次上舰的几条消息，合并完再提醒
+    guards: GuardMerger,
+}
+
+impl Alerter {
+    pub fn new(
+        rules: watch::Receiver<Vec<AlertRule>>,
+        keyword_rules: watch::Receiver<Vec<KeywordRule>>,
+        packets: broadcast::Receiver<Packet>,
+        went_live: broadcast::Receiver<u64>,
+    ) -> Result<Self> {
+        let client = HttpClient::builder().timeout(WEBHOOK_TIMEOUT).build()?;
+        let keywords = KeywordMatcher::new(&keyword_rules.borrow());
+        Ok(Self {
+            rules,
+            keyword_rules,
+            keywords,
+            packets,
+            went_live,
+            client,
+            last_sent: HashMap::new(),
+            sent_events: HashMap::new(),
+            danmu: HashMap::new(),
+            guards: GuardMerger::default(),
+        })
+    }
+
+    pub async fn start(self, stop: oneshot::Receiver<()>) -> Result<()> {
+        tokio::select! {
+            _ = stop => {
+                debug!("alerter stopped");
+                Ok(())
+            },
+            ret = self.run() => {
+                error!("alerter stopped unexpectedly: {:?}", ret);
+                ret
+            }
+        }
+    }
+
+    async fn run(mut self) -> Result<()> {
+        loop {
+            tokio::select! {
+                ret = self.rules.changed() => {
+                    ret?;
+                    // 规则的序号可能变了
+                    self.last_sent.clear();
+                    self.sent_events.clear();
+                    debug!("alert rules changed.");
+                }
+                Ok(()) = self.keyword_rules.changed() => {
+                    self.keywords = KeywordMatcher::new(&self.keyword_rules.borrow());
+                }
+                // 和 LIVE 消息一起时按冷却时间只提醒一次
+                Ok(room_id) = self.went_live.recv() => self.on_event(room_id, Event::Live),
+                recv = self.packets.recv() => match recv {
+                    Ok(packet) => {
+                        if self.rules.borrow().is_empty() {
+                            continue;
+                        }
+                        if let Err(e) = self.process_packet(packet) {
+                            warn!("alerter failed to process packet: {:?}", e);
+                        }
+                    }
+                    Err(RecvError::Lagged(cnt)) => {
+                        warn!("alerter lagged {} packets.", cnt);
+                    }
+                    Err(RecvError::Closed) => return Ok(()),
+                },
+            }
+        }
+    }
+
+    fn process_packet(&mut self, packet: Packet) -> Result<()> {
+        for guard in self.guards.flush(packet.time) {
+            let event = Event::Guard {
+                uid: guard.event.uid,
+                timestamp: guard.time.timestamp(),
+                user: guard.event.username,
+                gift: guard.event.gift_name,
+                level: guard.event.guard_level,
+                price: guard.event.price.yuan(),
+            };
+            self.on_event(guard.room_id, event);
+        }
+        match packet.operation {
+            Operation::Known(KnownOperation::SendMsgReply) => {}
+            _ => return Ok(()),
+        }
+        let msg =
+            serde_json::from_str::<SendMsgReply>(&packet.body).context("转换 SendMsgReply 失败")?;
+        let event = match msg.cmd.as_str() {
+            "LIVE" => Event::Live,
+            "SUPER_CHAT_MESSAGE" => {
+                let sc: SuperChat = serde_json::from_value(msg.data)?;
+                Event::SuperChat {
+                    id: sc.id(),
+                    user: sc.sender_name().to_string(),
+                    price: sc.price().yuan(),
+                    message: sc.message().to_string(),
+                }
+            }
+            "SEND_GIFT" => {
+                let gift: SendGift = serde_json::from_value(msg.data)?;
+                if let Some(guard) = gift.guard_event() {
+                    self.guards.push(packet.room_id, guard, packet.time);
+                    return Ok(());
+                }
+                if gift.is_free() {
+                    return Ok(());
+                }
+                Event::Gift {
+                    uid: gift.sender_id(),
+                    timestamp: gift.timestamp(),
+                    tid: gift.tid(),
+                    user: gift.sender_name().to_string(),
+                    gift: gift.gift_name().to_string(),
+                    price: gift.paid_price().yuan(),
+                }
+            }
+            "USER_TOAST_MSG" => {
+                let guard: UserToastMsg = serde_json::from_value(msg.data)?;
+                self.guards.push(packet.room_id, guard.into(), packet.time);
+                return Ok(());
+            }
+            "GUARD_BUY" => {
+                let guard: GuardBuy = serde_json::from_value(msg.data)?;
+                self.guards.push(packet.room_id, guard.into(), packet.time);
+                return Ok(());
+            }
+            cmd if cmd.starts_with("DANMU_MSG") => {
+                let danmu: Option<DanmuMsg> = serde_json::from_value(serde_json::json!({
+                    "cmd": cmd,
+                    "info": msg.info,
+                }))
+                .ok();
+                if let Some(danmu) = danmu {
+                    let keywords: Vec<String> = self
+                        .keywords
+                        .matches(packet.room_id, &danmu.text)
+                        .into_iter()
+                        .map(String::from)
+                        .collect();
+                    for keyword in keywords {
+                        let event = Event::Keyword {
+                            keyword,
+                            user: danmu.username.clone(),
+                            message: danmu.text.clone(),
+                        };
+                        self.on_event(packet.room_id, event);
+                    }
+                }
+                let per_minute = self.count_danmu(packet.room_id, packet.time.timestamp());
+                Event::DanmuRate { per_minute }
+            }
+            _ => return Ok(()),
+        };
+        self.on_event(packet.room_id, event);
+        Ok(())
+    }
+
+    /// 记一条弹幕，返回最近一分钟的弹幕数
+    fn count_danmu(&mut self, room_id: u64, secs: i64) -> u64 {
+        let window = self.danmu.entry(room_id).or_default();
+        match window.back_mut() {
+            Some((t, cnt)) if *t == secs => *cnt += 1,
+            _ => window.push_back((secs, 1)),
+        }
+        while let Some(&(t, _)) = window.front() {
+            if t > secs - DANMU_WINDOW_SECS {
+                break;
+            }
+            window.pop_front();
+        }
+        window.iter().map(|(_, cnt)| cnt).sum()
+    }
+
+    fn on_event(&mut self, room_id: u64, event: Event) {
+        let rules = self.rules.borrow().clone();
+        // rooms 里可能写的是短号
+        let watch_id = RoomInfo::from_cache_opt(room_id).map(|info| info.watch_id);
+        let identity = event.identity();
+        let now = Instant::now();
+        self.sent_events
+            .retain(|_, t| now.duration_since(*t) < DEDUPE_WINDOW);
+        for (i, rule) in rules.iter().enumerate() {
+            if !rule.applies_to(room_id, watch_id) {
+                continue;
+            }
+            if !event.matches(&rule.kind) {
+                continue;
+            }
+            match &identity {
+                Some(identity) => {
+                    let key = (i, identity.clone());
+                    if self.sent_events.contains_key(&key) {
+                        debug!("alert {} of {} already sent.", rule.kind.name(), identity);
+                        continue;
+                    }
+                    self.sent_events.insert(key, now);
+                }
+                None => {
+                    if let Some(last) = self.last_sent.get(&(i, room_id)) {
+                        if now.duration_since(*last) < Duration::from_secs(rule.cooldown) {
+                            debug!(
+                                "alert {} of room {} in cooldown.",
+                                rule.kind.name(),
+                                room_id
+                            );
+                            continue;
+                        }
+                    }
+                    self.last_sent.insert((i, room_id), now);
+                }
+            }
+
+            let body = render(&rule.body, room_id, rule.kind.name(), &event);
+            info!("alert {} of room {}: {}", rule.kind.name(), room_id, body);
+            let request = self
+                .client
+                .post(&rule.webhook)
+                .header(CONTENT_TYPE, "application/json")
+                .body(body);
+            let webhook = rule.webhook.clone();
+            // 不阻塞 packet 的处理
+            tokio::spawn(async move {
+                let ret = async { request.send().await?.error_for_status() }.await;
+                if let Err(e) = ret {
+                    warn!("failed to send alert to {}: {:?}", webhook, e);
+                }
+            });
+        }
+    }
+}
+
+/// 替换模板里的 `{name}`，值按 JSON 字符串转义
+fn render(template: &str, room_id: u64, kind: &str, event: &Event) -> String {
+    let room_info = RoomInfo::from_cache_opt(room_id);
+    let mut vars = vec![
+        ("room_id", room_id.to_string()),
+        ("kind", kind.to_string()),
+        (
+            "streamer",
+            room_info
+                .as_ref()
+                .map(|r| r.streamer.clone())
+                .unwrap_or_else(|| room_id.to_string()),
+        ),
+        (
+            "title",
+            room_info
+                .and_then(|r| r.activity)
+                .map(|a| a.title)
+                .unwrap_or_default(),
+        ),
+    ];
+    vars.extend(event.vars());
+
+    let mut body = template.to_string();
+    for (name, value) in vars {
+        let escaped = serde_json::to_string(&value).unwrap_or_default();
+        // 去掉两边的引号
+        let escaped = &escaped[1..escaped.len() - 1];
+        body = body.replace(&format!("{{{}}}", name), escaped);
+    }
+    body
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{packet, super_chat};
+    use hyper::{
+        service::{make_service_fn, service_fn},
+        Body, Request, Response, Server,
+    };
+    use parking_lot::Mutex;
+    use serde_json::Value;
+    use std::{convert::Infallible, net::SocketAddr, sync::Arc};
+
+    /// 本地的 webhook，记下收到的 body
+    async fn receiver() -> (String, Arc<Mutex<Vec<String>>>) {
+        let bodies: Arc<Mutex<Vec<String>>> = Default::default();
+        let server_bodies = bodies.clone();
+        let make_svc = make_service_fn(move |_conn| {
+            let bodies = server_bodies.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                    let bodies = bodies.clone();
+                    async move {
+                        let body = hyper::body::to_bytes(req.into_body()).await.unwrap();
+                        bodies
+                            .lock()
+                            .push(String::from_utf8(body.to_vec()).unwrap());
+                        Ok::<_, Infallible>(Response::new(Body::empty()))
+                    }
+                }))
+            }
+        });
+        let server = Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0))).serve(make_svc);
+        let webhook = format!("http://{}/hook", server.local_addr());
+        tokio::spawn(server);
+        (webhook, bodies)
+    }
+
+    fn alerter(rules: Vec<AlertRule>) -> Alerter {
+        let (_, rules) = watch::channel(rules);
+        let (_, keyword_rules) = watch::channel(Vec::<KeywordRule>::new());
+        let (_, packets) = broadcast::channel(16);
+        let (_, went_live) = broadcast::channel(16);
+        Alerter::new(rules, keyword_rules, packets, went_live).unwrap()
+    }
+
+    fn cache_room(room_id: u64, short_id: u64) {
+        let mut info = RoomInfo::new(room_id, "七海Nana7mi".to_string());
+        info.watch_id = short_id;
+        RoomInfo::write_cache(info);
+    }
+
+    fn gift(room_id: u64, timestamp: i64, tid: &str) -> Packet {
+        let mut msg: Value =
+            serde_json::from_str(include_str!("../fixtures/messages/send_gift_guard.json"))
+                .unwrap();
+        msg["data"]["giftId"] = 31039.into();
+        msg["data"]["giftName"] = "牛哇牛哇".into();
+        msg["data"]["price"] = 100000.into();
+        msg["data"]["timestamp"] = timestamp.into();
+        msg["data"]["tid"] = tid.into();
+        packet(room_id, msg)
+    }
+
+    /// 等 webhook 收到 n 个请求，再多等一会确认没有多余的，返回 text
+    async fn wait_for(bodies: &Mutex<Vec<String>>, n: usize) -> Vec<String> {
+        for _ in 0..100 {
+            if bodies.lock().len() >= n {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let mut texts: Vec<String> = bodies
+            .lock()
+            .iter()
+            .map(|body| {
+                let body: Value = serde_json::from_str(body).unwrap();
+                body["text"].as_str().unwrap().to_string()
+            })
+            .collect();
+        texts.sort();
+        texts
+    }
+
+    #[tokio::test]
+    async fn super_chats_are_rendered_and_deduped() {
+        const ROOM: u64 = 21452505;
+        const SHORT_ID: u64 = 92613;
+        cache_room(ROOM, SHORT_ID);
+        let (webhook, bodies) = receiver().await;
+        let mut alerter = alerter(vec![AlertRule {
+            kind: AlertKind::SuperChat { min_price: 30.0 },
+            webhook,
+            body: r#"{"text": "{streamer} 收到了 {user} 的 ￥{price} SC：{message}"}"#.to_string(),
+            // 写的是短号
+            rooms: vec![SHORT_ID],
+            cooldown: 300,
+        }]);
+
+        alerter.process_packet(super_chat(ROOM, 1, 50)).unwrap();
+        // 重复收到的同一条 SC
+        alerter.process_packet(super_chat(ROOM, 1, 50)).unwrap();
+        // 冷却时间内的另一条 SC 也要提醒
+        alerter.process_packet(super_chat(ROOM, 2, 100)).unwrap();
+        // 低于 min_price
+        alerter.process_packet(super_chat(ROOM, 3, 29)).unwrap();
+        // 其他直播间
+        alerter
+            .process_packet(super_chat(ROOM + 1, 4, 100))
+            .unwrap();
+
+        assert_eq!(
+            wait_for(&bodies, 2).await,
+            vec![
+                "七海Nana7mi 收到了 鱼塘里的鸽子 的 ￥100 SC：晚上好\"鲨鲨\"\n今天也辛苦了",
+                "七海Nana7mi 收到了 鱼塘里的鸽子 的 ￥50 SC：晚上好\"鲨鲨\"\n今天也辛苦了",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn gifts_are_deduped_by_tid() {
+        const ROOM: u64 = 22637261;
+        cache_room(ROOM, ROOM);
+        let (webhook, bodies) = receiver().await;
+        let mut alerter = alerter(vec![AlertRule {
+            kind: AlertKind::Gift { min_price: 100.0 },
+            webhook,
+            body: r#"{"text": "{user} 送出 {gift} ￥{price}"}"#.to_string(),
+            rooms: vec![],
+            cooldown: 300,
+        }]);
+
+        let first = "1624354023120600001";
+        alerter
+            .process_packet(gift(ROOM, 1624354023, first))
+            .unwrap();
+        alerter
+            .process_packet(gift(ROOM, 1624354023, first))
+            .unwrap();
+        // 同一秒内再送一次
+        alerter
+            .process_packet(gift(ROOM, 1624354023, "1624354023120600002"))
+            .unwrap();
+        alerter
+            .process_packet(gift(ROOM, 1624354024, "1624354024120600001"))
+            .unwrap();
+
+        assert_eq!(
+            wait_for(&bodies, 3).await,
+            vec![
+                "鱼塘里的鸽子 送出 牛哇牛哇 ￥100",
+                "鱼塘里的鸽子 送出 牛哇牛哇 ￥100",
+                "鱼塘里的鸽子 送出 牛哇牛哇 ￥100",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn polled_live_rooms_are_alerted() {
+        // 只在开播时连接时收不到 LIVE 消息，由 poller 通知
+        const ROOM: u64 = 21919321;
+        cache_room(ROOM, ROOM);
+        let (webhook, bodies) = receiver().await;
+        let (_, rules) = watch::channel(vec![AlertRule {
+            kind: AlertKind::Live,
+            webhook,
+            body: r#"{"text": "{streamer} 开播了"}"#.to_string(),
+            rooms: vec![],
+            cooldown: 300,
+        }]);
+        let (_, keyword_rules) = watch::channel(Vec::<KeywordRule>::new());
+        let (packet_sender, packets) = broadcast::channel(16);
+        let (went_live_sender, went_live) = broadcast::channel(16);
+        let alerter = Alerter::new(rules, keyword_rules, packets, went_live).unwrap();
+        let (_stop, stop) = oneshot::channel();
+        tokio::spawn(alerter.start(stop));
+
+        went_live_sender.send(ROOM).unwrap();
+        // 同时收到的 LIVE 消息在冷却时间内
+        packet_sender
+            .send(packet(ROOM, serde_json::json!({"cmd": "LIVE"})))
+            .unwrap();
+
+        assert_eq!(wait_for(&bodies, 1).await, vec!["七海Nana7mi 开播了"]);
+    }
+}
@@ -0,0 +1,205 @@
+//! 按分区自动发现直播间
+//!
+//! 定期拉取分区的直播间列表（按人气排序），按规则选出直播间，和 watch.toml 里的直播间合并后交给 manager。
+
+use std::{collections::HashMap, time::Duration};
+
+use anyhow::Result;
+use tokio::sync::{oneshot, watch};
+
+use crate::{http::BiliHttp, requests::AreaRoomList, task_factory::TaskSet};
+
+/// 每页之间的间隔，避免请求太快
+const PAGE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// watch.toml 里的 `[discovery]`
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub struct DiscoveryConfig {
+    /// 刷新间隔（秒）
+    #[serde(default = "default_interval")]
+    pub interval: u64,
+    /// 自动发现的直播间上限
+    #[serde(default = "default_max_rooms")]
+    pub max_rooms: usize,
+    /// 连续多少轮不满足条件才移除，避免直播间反复进出
+    #[serde(default = "default_keep_rounds")]
+    pub keep_rounds: u32,
+    #[serde(default, rename = "area")]
+    pub areas: Vec<AreaRule>,
+}
+
+/// 一个分区的选择规则
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub struct AreaRule {
+    pub parent_area_id: u64,
+    /// 0 为整个父分区
+    #[serde(default)]
+    pub area_id: u64,
+    /// 最低人气值
+    #[serde(default)]
+    pub min_popularity: u64,
+    /// 只取分区人气排名前多少的直播间
+    #[serde(default = "default_max_rank")]
+    pub max_rank: usize,
+}
+
+fn default_interval() -> u64 {
+    5 * 60
+}
+fn default_max_rooms() -> usize {
+    50
+}
+fn default_keep_rounds() -> u32 {
+    3
+}
+fn default_max_rank() -> usize {
+    30
+}
+
+pub struct Discovery {
+    config: watch::Receiver<Option<DiscoveryConfig>>,
+    publish: watch::Sender<TaskSet>,
+    http: BiliHttp,
+    /// 已发现的直播间 => 连续未命中的轮数
+    discovered: HashMap<u64, u32>,
+}
+
+impl Discovery {
+    pub fn new(
+        config: watch::Receiver<Option<DiscoveryConfig>>,
+        publish: watch::Sender<TaskSet>,
+        http: BiliHttp,
+    ) -> Self {
+        Self {
+            config,
+            publish,
+            http,
+            discovered: HashMap::new(),
+        }
+    }
+
+    pub async fn start(self, stop: oneshot::Receiver<()>) -> Result<()> {
+        tokio::select! {
+            _ = stop => {
+                debug!("discovery stopped");
+                Ok(())
+            },
+            ret = self.run() => {
+                error!("discovery stopped unexpectedly: {:?}", ret);
+                ret
+            }
+        }
+    }
+
+    async fn run(mut self) -> Result<()> {
+        loop {
+            let config = self.config.borrow().clone();
+            match config {
+                Some(config) => {
+                    self.discover(&config).await;
+                    tokio::select! {
+                        _ = tokio::time::sleep(Duration::from_secs(config.interval)) => {}
+                        ret = self.config.changed() => {
+                            ret?;
+                            debug!("discovery config changed.");
+                        }
+                    }
+                }
+                None => {
+                    if !self.discovered.is_empty() {
+                        info!("discovery disabled, removing all discovered rooms.");
+                        self.discovered.clear();
+                        self.publish()?;
+                    }
+                    // 等到有配置
+                    self.config.changed().await?;
+                }
+            }
+        }
+    }
+
+    async fn discover(&mut self, config: &DiscoveryConfig) {
+        // room_id => popularity
+        let mut matched: HashMap<u64, u64> = HashMap::new();
+        for rule in config.areas.iter() {
+            match self.fetch_area(rule).await {
+                Ok(rooms) => matched.extend(rooms),
+                Err(e) => {
+                    // 拉取失败时跳过这一轮，不计为未命中
+                    warn!("failed to fetch area {:?}: {:?}", rule, e);
+                    return;
+                }
+            }
+        }
+
+        // 已有的直播间：命中则清零，否则累计
+        self.discovered.retain(|room_id, missed| {
+            if matched.contains_key(room_id) {
+                *missed = 0;
+                return true;
+            }
+            *missed += 1;
+            if *missed > config.keep_rounds {
+                info!(
+                    "room {} no longer matches discovery rules, removing.",
+                    room_id
+                );
+                false
+            } else {
+                true
+            }
+        });
+        // 新的直播间：按人气从高到低加入，直到上限
+        let mut candidates: Vec<_> = matched
+            .into_iter()
+            .filter(|(room_id, _)| !self.discovered.contains_key(room_id))
+            .collect();
+        candidates.sort_by_key(|(_, popularity)| std::cmp::Reverse(*popularity));
+        for (room_id, popularity) in candidates {
+            if self.discovered.len() >= config.max_rooms {
+                debug!("discovery reached max rooms {}.", config.max_rooms);
+                break;
+            }
+            info!("discovered room {} (popularity {}).", room_id, popularity);
+            self.discovered.insert(room_id, 0);
+        }
+
+        if let Err(e) = self.publish() {
+            error!("failed to publish discovered rooms: {:?}", e);
+        }
+    }
+
+    /// 返回满足规则的 (room_id, popularity)
+    async fn fetch_area(&self, rule: &AreaRule) -> Result<Vec<(u64, u64)>> {
+        let mut rooms = vec![];
+        let mut page = 1;
+        while rooms.len() < rule.max_rank {
+            let list =
+                AreaRoomList::request(&self.http, rule.parent_area_id, rule.area_id, page).await?;
+            rooms.extend(list.list);
+            if list.has_more == 0 {
+                break;
+            }
+            page += 1;
+            tokio::time::sleep(PAGE_INTERVAL).await;
+        }
+        debug!(
+            "area {}/{} fetched {} rooms.",
+            rule.parent_area_id,
+            rule.area_id,
+            rooms.len()
+        );
+        Ok(rooms
+            .into_iter()
+            .take(rule.max_rank)
+            .filter(|room| room.online >= rule.min_popularity)
+            .map(|room| (room.room_id, room.online))
+            .collect())
+    }
+
+    fn publish(&self) -> Result<()> {
+        let rooms: TaskSet = self.discovered.keys().cloned().collect();
+        self.publish.send(rooms)?;
+        Ok(())
+    }
+}
@@ -24,6 +24,8 @@ pub struct FileAppender<T> {
     writer: Writer,
     writer_date: Date<Tz>,
     receiver: broadcast::Receiver<T>,
+    /// 返回 false 的不写入文件
+    filter: Option<fn(&T) -> bool>,
 }
 
 impl<T: Serialize + Clone> FileAppender<T> {
@@ -35,9 +37,15 @@ impl<T: Serialize + Clone> FileAppender<T> {
             writer,
             writer_date: date,
             receiver,
+            filter: None,
         })
     }
 
+    pub fn filter(mut self, filter: fn(&T) -> bool) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+
     pub async fn start(mut self) -> Result<()> {
         let r = self.start_writer().await;
         debug!("file appender {} closing.", self.path);
@@ -76,6 +84,11 @@ impl<T: Serialize + Clone> FileAppender<T> {
         loop {
             match self.receiver.recv().await {
                 Ok(packet) => {
+                    if let Some(filter) = self.filter {
+                        if !filter(&packet) {
+                            continue;
+                        }
+                    }
                     self.write_packet(packet).await?;
                     items += 1;
                     if items % 1_000 == 0 || Instant::now().duration_since(last_flush) > MAX_FLUSH {
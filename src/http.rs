@@ -0,0 +1,406 @@
+//! 所有 b 站 http 请求共用的一层
+//!
+//! - 令牌桶限速，启动时大量直播间同时请求也不会超过设定的速率
+//! - 可选的代理列表，按请求轮换
+//! - 发现 412（被风控）时所有请求暂停一段时间
+//! - 可选的 base url，把请求发到镜像或者本地的测试服务
+//!
+//! [`BiliHttp`] 可以 clone，clone 之间共用限速和暂停状态。
+
+use std::{
+    fmt::{self, Display, Formatter},
+    future::Future,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use anyhow::{bail, Context, Result};
+use parking_lot::Mutex;
+use reqwest::{Client, ClientBuilder, StatusCode, Url};
+use tokio::time::Instant;
+
+/// 限速和代理的设置
+#[derive(Debug, Clone)]
+pub struct HttpConfig {
+    /// 每秒请求数
+    pub rate: f64,
+    /// 令牌桶容量，允许的突发请求数
+    pub burst: u32,
+    /// 代理地址，为空时直连
+    pub proxies: Vec<String>,
+    /// 被风控后暂停多久
+    pub ban_cooldown: Duration,
+    /// 替换请求的协议、域名和端口，为 None 时请求 b 站
+    pub base_url: Option<String>,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            rate: 5.0,
+            burst: 10,
+            proxies: vec![],
+            ban_cooldown: Duration::from_secs(5 * 60),
+            base_url: None,
+        }
+    }
+}
+
+struct Limiter {
+    rate: f64,
+    burst: f64,
+    ban_cooldown: Duration,
+    /// (当前令牌数, 上次补充的时间)
+    bucket: Mutex<(f64, Instant)>,
+    /// 风控暂停到什么时候
+    cooldown_until: Mutex<Option<Instant>>,
+}
+
+impl Limiter {
+    /// 等到可以发出一个请求
+    async fn acquire(&self) {
+        loop {
+            let cooldown = *self.cooldown_until.lock();
+            if let Some(until) = cooldown {
+                if until > Instant::now() {
+                    tokio::time::sleep_until(until).await;
+                    continue;
+                }
+            }
+
+            let wait = {
+                let mut bucket = self.bucket.lock();
+                let (tokens, last) = &mut *bucket;
+                let now = Instant::now();
+                *tokens =
+                    (*tokens + now.duration_since(*last).as_secs_f64() * self.rate).min(self.burst);
+                *last = now;
+                if *tokens >= 1.0 {
+                    *tokens -= 1.0;
+                    return;
+                }
+                Duration::from_secs_f64((1.0 - *tokens) / self.rate)
+            };
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    fn on_banned(&self) {
+        let mut cooldown = self.cooldown_until.lock();
+        let until = Instant::now() + self.ban_cooldown;
+        if cooldown.map(|t| t < until).unwrap_or(true) {
+            warn!(
+                "bilibili api returned 412, pausing all requests for {:?}",
+                self.ban_cooldown
+            );
+            *cooldown = Some(until);
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct BiliHttp {
+    limiter: Arc<Limiter>,
+    proxies: Arc<Vec<String>>,
+    /// 每个代理一个 client，没有代理时只有一个
+    clients: Arc<Vec<Client>>,
+    next: Arc<AtomicUsize>,
+    base_url: Option<Arc<Url>>,
+}
+
+impl BiliHttp {
+    pub fn new(config: HttpConfig) -> Result<Self> {
+        // 速率为 0、负数或者 NaN 时令牌永远补不上，计算等待时间也会 panic
+        if !(config.rate > 0.0 && config.rate.is_finite()) {
+            bail!("http rate must be a positive number, got {}", config.rate);
+        }
+        let limiter = Limiter {
+            rate: config.rate,
+            burst: config.burst.max(1) as f64,
+            ban_cooldown: config.ban_cooldown,
+            bucket: Mutex::new((config.burst.max(1) as f64, Instant::now())),
+            cooldown_until: Mutex::new(None),
+        };
+        let base_url = match &config.base_url {
+            Some(base_url) => {
+                let url = Url::parse(base_url)
+                    .with_context(|| format!("invalid base url {}", base_url))?;
+                info!("bilibili requests are sent to {}", url);
+                Some(Arc::new(url))
+            }
+            None => None,
+        };
+        let proxies = Arc::new(config.proxies);
+        let clients = Arc::new(Self::build_clients(&proxies, default_builder)?);
+        if !proxies.is_empty() {
+            info!("bilibili requests rotate through {} proxies", proxies.len());
+        }
+        Ok(Self {
+            limiter: Arc::new(limiter),
+            proxies,
+            clients,
+            next: Default::default(),
+            base_url,
+        })
+    }
+
+    /// 共用限速，但用另外的 client（例如带 cookie 的）
+    pub fn with_clients(&self, builder: impl Fn() -> ClientBuilder) -> Result<Self> {
+        Ok(Self {
+            limiter: self.limiter.clone(),
+            proxies: self.proxies.clone(),
+            clients: Arc::new(Self::build_clients(&self.proxies, builder)?),
+            next: Default::default(),
+            base_url: self.base_url.clone(),
+        })
+    }
+
+    fn build_clients(
+        proxies: &[String],
+        builder: impl Fn() -> ClientBuilder,
+    ) -> Result<Vec<Client>> {
+        if proxies.is_empty() {
+            return Ok(vec![builder().build()?]);
+        }
+        proxies
+            .iter()
+            .map(|proxy| {
+                let proxy = reqwest::Proxy::all(proxy.as_str())
+                    .with_context(|| format!("invalid proxy {}", proxy))?;
+                Ok(builder().proxy(proxy).build()?)
+            })
+            .collect()
+    }
+
+    /// 轮换到下一个 client
+    fn next_client(&self) -> Client {
+        let i = self.next.fetch_add(1, Ordering::Relaxed) % self.clients.len();
+        self.clients[i].clone()
+    }
+
+    /// 设置了 base url 时替换掉 b 站的域名，保留路径和参数
+    pub fn url(&self, url: &str) -> Result<Url> {
+        let mut url = Url::parse(url).with_context(|| format!("invalid url {}", url))?;
+        if let Some(base_url) = &self.base_url {
+            let path = format!("{}{}", base_url.path().trim_end_matches('/'), url.path());
+            let query = url.query().map(String::from);
+            url = (**base_url).clone();
+            url.set_path(&path);
+            url.set_query(query.as_deref());
+        }
+        Ok(url)
+    }
+
+    /// 限速后用一个 client 发出请求，失败时检查是否被风控
+    pub async fn request<T, E, F, Fut>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(Client) -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+        E: Into<anyhow::Error>,
+    {
+        self.limiter.acquire().await;
+        let ret = f(self.next_client()).await.map_err(Into::into);
+        if let Err(e) = &ret {
+            if is_banned(e) {
+                self.limiter.on_banned();
+            }
+        }
+        ret
+    }
+}
+
+/// 接口返回的风控 code
+const BANNED_CODE: i64 = -412;
+
+/// b 站接口返回了非 0 的 code
+#[derive(Debug)]
+pub struct ApiError {
+    pub url: String,
+    pub code: i64,
+    pub message: String,
+}
+
+impl Display for ApiError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_fmt(format_args!(
+            "bilibili api {} error {}: {}",
+            self.url, self.code, self.message
+        ))
+    }
+}
+impl std::error::Error for ApiError {}
+
+/// HTTP 412 或者接口返回 -412 是 b 站风控
+fn is_banned(e: &anyhow::Error) -> bool {
+    e.chain().any(|e| {
+        if let Some(e) = e.downcast_ref::<ApiError>() {
+            return e.code == BANNED_CODE;
+        }
+        match e.downcast_ref::<reqwest::Error>() {
+            Some(e) => e.status() == Some(StatusCode::PRECONDITION_FAILED),
+            None => false,
+        }
+    })
+}
+
+/// 和浏览器一样的 user agent
+pub fn default_builder() -> ClientBuilder {
+    ClientBuilder::new().user_agent("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/91.0.4472.164 Safari/537.36")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::requests::get;
+    use hyper::{
+        service::{make_service_fn, service_fn},
+        Body, Request, Response, Server,
+    };
+    use std::{convert::Infallible, net::SocketAddr};
+
+    /// 本地的假接口，记下每个请求到达的时间
+    async fn mock_server() -> (String, Arc<Mutex<Vec<Instant>>>) {
+        let hits: Arc<Mutex<Vec<Instant>>> = Default::default();
+        let server_hits = hits.clone();
+        let make_svc = make_service_fn(move |_conn| {
+            let hits = server_hits.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                    hits.lock().push(Instant::now());
+                    let body = match req.uri().path() {
+                        "/x/banned" => None,
+                        "/x/code-banned" => Some(r#"{"code":-412,"message":"请求被拦截"}"#),
+                        "/x/error" => Some(r#"{"code":-400,"message":"请求错误"}"#),
+                        _ => Some(r#"{"code":0,"message":"0","data":1}"#),
+                    };
+                    let resp = match body {
+                        Some(body) => Response::new(Body::from(body)),
+                        None => Response::builder()
+                            .status(StatusCode::PRECONDITION_FAILED)
+                            .body(Body::empty())
+                            .unwrap(),
+                    };
+                    async move { Ok::<_, Infallible>(resp) }
+                }))
+            }
+        });
+        let server = Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0))).serve(make_svc);
+        let base_url = format!("http://{}", server.local_addr());
+        tokio::spawn(server);
+        (base_url, hits)
+    }
+
+    fn limited_http(base_url: &str, rate: f64, burst: u32, ban_cooldown: Duration) -> BiliHttp {
+        BiliHttp::new(HttpConfig {
+            rate,
+            burst,
+            proxies: vec![],
+            ban_cooldown,
+            base_url: Some(base_url.to_string()),
+        })
+        .unwrap()
+    }
+
+    async fn get_ok(http: &BiliHttp) {
+        let data: u32 = get(http, "https://api.bilibili.com/x/ok", &[])
+            .await
+            .unwrap();
+        assert_eq!(data, 1);
+    }
+
+    #[test]
+    fn base_url_keeps_path_and_query() {
+        let http = BiliHttp::new(HttpConfig {
+            base_url: Some("http://127.0.0.1:8080/mirror/".to_string()),
+            ..Default::default()
+        })
+        .unwrap();
+        let url = http
+            .url("https://api.live.bilibili.com/room/v1/Room/get_info?room_id=1")
+            .unwrap();
+        assert_eq!(
+            url.as_str(),
+            "http://127.0.0.1:8080/mirror/room/v1/Room/get_info?room_id=1"
+        );
+
+        let http = BiliHttp::new(HttpConfig::default()).unwrap();
+        let url = http.url("https://api.bilibili.com/x/ok?a=1").unwrap();
+        assert_eq!(url.as_str(), "https://api.bilibili.com/x/ok?a=1");
+    }
+
+    #[test]
+    fn invalid_rate_is_rejected() {
+        for &rate in &[0.0, -1.0, f64::NAN, f64::INFINITY] {
+            let config = HttpConfig {
+                rate,
+                ..Default::default()
+            };
+            assert!(BiliHttp::new(config).is_err(), "{}", rate);
+        }
+    }
+
+    #[tokio::test]
+    async fn rate_and_burst() {
+        let (base_url, hits) = mock_server().await;
+        let http = limited_http(&base_url, 20.0, 3, Duration::from_secs(60));
+        let start = Instant::now();
+        for _ in 0..6 {
+            get_ok(&http).await;
+        }
+        let hits = hits.lock();
+        assert_eq!(hits.len(), 6);
+        // 桶里的令牌立即可用
+        assert!(
+            hits[2] - start < Duration::from_millis(40),
+            "{:?}",
+            hits[2] - start
+        );
+        // 之后每 50ms 一个
+        assert!(
+            hits[3] - start >= Duration::from_millis(40),
+            "{:?}",
+            hits[3] - start
+        );
+        assert!(
+            hits[5] - start >= Duration::from_millis(140),
+            "{:?}",
+            hits[5] - start
+        );
+    }
+
+    #[tokio::test]
+    async fn pause_after_banned() {
+        let cooldown = Duration::from_millis(300);
+        for path in &["/x/banned", "/x/code-banned"] {
+            let (base_url, hits) = mock_server().await;
+            let http = limited_http(&base_url, 1000.0, 10, cooldown);
+            let url = format!("https://api.bilibili.com{}", path);
+            assert!(get::<u32>(&http, &url, &[]).await.is_err());
+            // clone 之间共用暂停状态
+            get_ok(&http.clone()).await;
+            let hits = hits.lock();
+            assert!(
+                hits[1] - hits[0] >= cooldown,
+                "{}: {:?}",
+                path,
+                hits[1] - hits[0]
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn other_errors_do_not_pause() {
+        let (base_url, hits) = mock_server().await;
+        let http = limited_http(&base_url, 1000.0, 10, Duration::from_secs(60));
+        let err = get::<u32>(&http, "https://api.bilibili.com/x/error", &[])
+            .await
+            .unwrap_err();
+        assert_eq!(err.downcast_ref::<ApiError>().unwrap().code, -400);
+        get_ok(&http).await;
+        let hits = hits.lock();
+        assert!(hits[1] - hits[0] < Duration::from_secs(1));
+    }
+}
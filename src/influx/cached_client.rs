@@ -1,5 +1,5 @@
 use anyhow::Result;
-use influxdb_client::{Client as InfluxClient, Point, TimestampOptions};
+use influxdb_client::Point;
 use parking_lot::Mutex;
 use std::{
     sync::Arc,
@@ -7,10 +7,12 @@ use std::{
 };
 use tokio::time::sleep;
 
+use super::InfluxSink;
+
 const DEFAULT_CACHE_SIZE: usize = 32;
 
 pub struct CachedInfluxClient {
-    client: InfluxClient,
+    client: InfluxSink,
 
     /// 从开始到现在成功插入的数量
     insert_count: u64,
@@ -28,7 +30,7 @@ pub struct CachedInfluxClient {
 }
 
 impl CachedInfluxClient {
-    pub fn new(client: InfluxClient) -> Self {
+    pub fn new(client: InfluxSink) -> Self {
         Self {
             insert_count: 0,
             fail_count: Default::default(),
@@ -44,6 +46,10 @@ impl CachedInfluxClient {
         self
     }
 
+    pub fn get_buffer_size(&self) -> usize {
+        self.buffer_size
+    }
+
     #[allow(unused)]
     pub fn async_write(mut self, async_write: bool) -> Self {
         self.async_write = async_write;
@@ -77,7 +83,8 @@ impl CachedInfluxClient {
             points.len(),
             self.async_write
         );
-        if self.async_write {
+        // 文件需要按顺序写入
+        if self.async_write && !self.client.is_file() {
             let client = self.client.clone();
             let fail_count = self.fail_count.clone();
             let fut = async move {
@@ -100,20 +107,28 @@ impl CachedInfluxClient {
         Ok(())
     }
 
-    /// 同步写入数据点，会重试三次
-    async fn insert_points_retry_sync(client: &InfluxClient, points: &[Point]) -> Result<()> {
+    /// 写入剩下的数据点并关闭输出，写文件时会写完 gzip 的结尾
+    pub async fn shutdown(&mut self) -> Result<()> {
+        self.flush().await?;
+        self.client.shutdown().await
+    }
+
+    /// 同步写入数据点，数据库会重试三次
+    async fn insert_points_retry_sync(client: &InfluxSink, points: &[Point]) -> Result<()> {
         let t = Instant::now();
         const RETRY_DELAY: [Duration; 3] = [
             Duration::from_secs(0),
             Duration::from_secs(1),
             Duration::from_secs(3),
         ];
-        #[allow(clippy::needless_range_loop)]
-        for i in 0..RETRY_DELAY.len() + 1 {
-            match client
-                .insert_points(points, TimestampOptions::FromPoint)
-                .await
-            {
+        // 文件可能已经写进去一部分，重试会重复写入
+        let retries = if client.is_file() {
+            0
+        } else {
+            RETRY_DELAY.len()
+        };
+        for i in 0..retries + 1 {
+            match client.write(points).await {
                 Ok(_) => {
                     if i > 0 {
                         info!(
@@ -133,9 +148,9 @@ impl CachedInfluxClient {
                 }
                 Err(e) => {
                     warn!("Error insert to influxdb: {:?}", e);
-                    if i == RETRY_DELAY.len() {
+                    if i == retries {
                         error!("Insert to influx failed!");
-                        return Err(e.into());
+                        return Err(e);
                     }
                     let delay = RETRY_DELAY[i];
                     info!("will retry after {} seconds.", delay.as_secs());
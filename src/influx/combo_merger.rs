@@ -0,0 +1,163 @@
+use chrono::{DateTime, Duration, Local};
+use influxdb_client::Point;
+
+use super::{
+    messages::{ComboEnd, SendGift, ToPoint},
+    RoomInfo,
+};
+
+/// 连击最后一个礼物之后多久没有 COMBO_END 也认为结束（秒）
+const COMBO_TIMEOUT_SECS: i64 = 10;
+
+struct Pending {
+    room_id: u64,
+    /// 连击开始的时间，也是写入的时间
+    first: DateTime<Local>,
+    last: DateTime<Local>,
+    /// 数量累加后的礼物
+    gift: SendGift,
+}
+
+/// 把一次连击的多个 SEND_GIFT 合并为一个点
+///
+/// 按 batch_combo_id 和礼物名合并（盲盒连击会爆出不同的礼物），收到 COMBO_END 或超时后写入。
+#[derive(Default)]
+pub struct ComboMerger {
+    pending: Vec<Pending>,
+}
+
+impl ComboMerger {
+    /// 不是连击的礼物原样返回
+    pub fn push(&mut self, room_id: u64, gift: SendGift, t: DateTime<Local>) -> Option<SendGift> {
+        let combo_id = match gift.combo_id() {
+            Some(combo_id) => combo_id,
+            None => return Some(gift),
+        };
+        let same = self.pending.iter_mut().find(|p| {
+            p.room_id == room_id
+                && p.gift.combo_id() == Some(combo_id)
+                && p.gift.gift_name() == gift.gift_name()
+        });
+        match same {
+            Some(pending) => {
+                pending.gift.add_num(&gift);
+                pending.last = t;
+            }
+            None => self.pending.push(Pending {
+                room_id,
+                first: t,
+                last: t,
+                gift,
+            }),
+        }
+        None
+    }
+
+    /// 收到 COMBO_END，取出这次连击的所有礼物
+    ///
+    /// 盲盒连击的 COMBO_END 里是盲盒的名字，只能按 batch_combo_id 匹配；
+    /// 没有 batch_combo_id 时才按用户和礼物名匹配
+    pub fn end(&mut self, room_id: u64, end: &ComboEnd) -> Vec<Point> {
+        let (finished, pending) = std::mem::take(&mut self.pending)
+            .into_iter()
+            .partition(|p| {
+                p.room_id == room_id
+                    && if end.batch_combo_id.is_empty() {
+                        p.gift.sender_id() == end.uid && p.gift.gift_name() == end.gift_name
+                    } else {
+                        p.gift.combo_id() == Some(end.batch_combo_id.as_str())
+                    }
+            });
+        self.pending = pending;
+        Self::into_points(finished)
+    }
+
+    /// 取出超时的连击，now 为最近收到的 packet 时间
+    pub fn flush(&mut self, now: DateTime<Local>) -> Vec<Point> {
+        let timeout = Duration::seconds(COMBO_TIMEOUT_SECS);
+        let (finished, pending) = std::mem::take(&mut self.pending)
+            .into_iter()
+            .partition(|p| now - p.last > timeout);
+        self.pending = pending;
+        Self::into_points(finished)
+    }
+
+    /// 退出时取出所有连击
+    pub fn flush_all(&mut self) -> Vec<Point> {
+        Self::into_points(std::mem::take(&mut self.pending))
+    }
+
+    fn into_points(finished: Vec<Pending>) -> Vec<Point> {
+        finished
+            .into_iter()
+            .map(|p| {
+                let room_info = RoomInfo::from_cache(p.room_id);
+                info!("连击: {} @ {}", p.gift, room_info.streamer);
+                p.gift.into_point(&room_info, p.first)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    /// 盲盒连击里的一个礼物
+    fn blind_gift(combo_id: &str, gift_name: &str) -> SendGift {
+        let mut msg: Value =
+            serde_json::from_str(include_str!("../../fixtures/messages/send_gift_guard.json"))
+                .unwrap();
+        let data = &mut msg["data"];
+        data["giftId"] = 32126.into();
+        data["giftName"] = gift_name.into();
+        data["price"] = 1000.into();
+        data["batch_combo_id"] = combo_id.into();
+        data["blind_gift"] = serde_json::json!({
+            "original_gift_name": "心动盲盒",
+            "original_gift_price": 15000,
+        });
+        serde_json::from_value(msg["data"].take()).unwrap()
+    }
+
+    /// 和 fixture 里送礼的是同一个用户
+    fn combo_end(gift_name: &str, combo_id: &str) -> ComboEnd {
+        serde_json::from_value(serde_json::json!({
+            "uid": 35473219,
+            "gift_name": gift_name,
+            "batch_combo_id": combo_id,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn blind_box_combo_ends_by_combo_id() {
+        let mut merger = ComboMerger::default();
+        let t = Local::now();
+        for (combo_id, gift_name) in [
+            ("batch:gift:combo_id:1", "星河入梦"),
+            ("batch:gift:combo_id:1", "小花花"),
+            ("batch:gift:combo_id:1", "小花花"),
+            ("batch:gift:combo_id:2", "小花花"),
+        ] {
+            assert!(merger.push(1, blind_gift(combo_id, gift_name), t).is_none());
+        }
+
+        let end = combo_end("心动盲盒", "batch:gift:combo_id:1");
+        // 另一个直播间的同一个连击 id 不算
+        assert!(merger.end(2, &end).is_empty());
+        // 爆出的两种礼物各一个点
+        assert_eq!(merger.end(1, &end).len(), 2);
+        assert_eq!(merger.flush_all().len(), 1);
+    }
+
+    #[test]
+    fn combo_end_without_id_matches_gift_name() {
+        let mut merger = ComboMerger::default();
+        let gift = blind_gift("batch:gift:combo_id:1", "小花花");
+        assert!(merger.push(1, gift, Local::now()).is_none());
+        let end = combo_end("小花花", "");
+        assert_eq!(merger.end(1, &end).len(), 1);
+    }
+}
@@ -0,0 +1,238 @@
+//! influxdb 的连接设置、measurement 名字和全局 tag
+//!
+//! 设置来自 `--influx-config` 指定的 toml 文件（可以不存在），再由环境变量覆盖。
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use influxdb_client::{Client as InfluxClient, Point, Precision};
+use parking_lot::RwLock;
+
+use super::InfluxSink;
+
+/// 所有 measurement 的名字，写入时会加上 `measurement_prefix`
+pub mod measurement {
+    pub const GIFT: &str = "live-gift";
+    pub const POPULARITY: &str = "live-popularity";
+    pub const MODERATION: &str = "live-moderation";
+    pub const KEYWORD: &str = "live-keyword";
+    pub const GUARD: &str = "live-guard";
+    pub const ROOM: &str = "live-room";
+    pub const BILI_INFO: &str = "bili-info";
+    pub const BILI_VIDEO: &str = "bili-video";
+}
+
+/// 写入的接口
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InfluxApi {
+    /// influxdb 2.x
+    V2,
+    /// influxdb 1.x 或 VictoriaMetrics 的 `/write?db=`
+    V1,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct InfluxConfig {
+    pub api: InfluxApi,
+    /// 带协议的地址，https 即为 TLS
+    pub url: String,
+    /// v2 的 token
+    pub token: Option<String>,
+    pub org: String,
+    /// v1 时为 database
+    pub bucket: String,
+    /// v1 的 retention policy
+    pub retention_policy: Option<String>,
+    /// v1 的用户名和密码
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// 不连接数据库，写入这个 gzip 的 line protocol 文件
+    pub file: Option<PathBuf>,
+    /// 爬虫的数据写入另一个 bucket，不写时和直播数据相同
+    pub spider_bucket: Option<String>,
+    /// 所有 measurement 名字的前缀
+    pub measurement_prefix: String,
+    /// 加到每个点上的 tag，例如录制的机器
+    pub tags: BTreeMap<String, String>,
+}
+
+impl Default for InfluxConfig {
+    fn default() -> Self {
+        Self {
+            api: InfluxApi::V2,
+            url: "http://127.0.0.1:8086".to_string(),
+            token: None,
+            org: "ddpanel".to_string(),
+            bucket: "ddpanel".to_string(),
+            retention_policy: None,
+            username: None,
+            password: None,
+            file: None,
+            spider_bucket: None,
+            measurement_prefix: String::new(),
+            tags: BTreeMap::new(),
+        }
+    }
+}
+
+/// (measurement 前缀, 全局 tag)
+type PointTemplate = (String, Vec<(String, String)>);
+
+lazy_static::lazy_static! {
+    static ref POINT_TEMPLATE: RwLock<PointTemplate> = Default::default();
+}
+
+impl InfluxConfig {
+    /// 读取设置文件，文件不存在时使用默认值，然后读取环境变量
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let (mut config, url_configured) = if path.exists() {
+            let s = std::fs::read_to_string(path)?;
+            let value: toml::Value =
+                toml::from_str(&s).with_context(|| format!("failed to parse {:?}", path))?;
+            let url_configured = value.get("url").is_some();
+            let config = value
+                .try_into()
+                .with_context(|| format!("failed to parse {:?}", path))?;
+            (config, url_configured)
+        } else {
+            debug!("influx config {:?} not found, using env only", path);
+            (Self::default(), false)
+        };
+        config.apply_env(url_configured);
+        Ok(config)
+    }
+
+    /// url_configured 为设置文件里是否写了 url
+    fn apply_env(&mut self, url_configured: bool) {
+        let env = |key: &str| std::env::var(key).ok().filter(|v| !v.is_empty());
+        // 兼容以前只有 host:port 的 INFLUX_ADDR，只在没有设置 url 时使用
+        if let Some(addr) = env("INFLUX_ADDR") {
+            if url_configured {
+                debug!("INFLUX_ADDR is ignored since url is set in the config file");
+            } else {
+                self.url = format!("http://{}", addr);
+            }
+        }
+        if let Some(url) = env("INFLUX_URL") {
+            self.url = url;
+        }
+        match env("INFLUX_API").as_deref() {
+            Some("v1") => self.api = InfluxApi::V1,
+            Some("v2") => self.api = InfluxApi::V2,
+            Some(api) => warn!("unknown INFLUX_API {:?}, expect v1 or v2", api),
+            None => {}
+        }
+        if let Some(rp) = env("INFLUX_RETENTION_POLICY") {
+            self.retention_policy = Some(rp);
+        }
+        if let Some(username) = env("INFLUX_USERNAME") {
+            self.username = Some(username);
+        }
+        if let Some(password) = env("INFLUX_PASSWORD") {
+            self.password = Some(password);
+        }
+        if let Some(file) = env("INFLUX_FILE") {
+            self.file = Some(file.into());
+        }
+        if let Some(token) = env("INFLUX_TOKEN") {
+            self.token = Some(token);
+        }
+        if let Some(org) = env("INFLUX_ORG") {
+            self.org = org;
+        }
+        if let Some(bucket) = env("INFLUX_BUCKET") {
+            self.bucket = bucket;
+        }
+        if let Some(bucket) = env("INFLUX_SPIDER_BUCKET") {
+            self.spider_bucket = Some(bucket);
+        }
+        if let Some(prefix) = env("INFLUX_MEASUREMENT_PREFIX") {
+            self.measurement_prefix = prefix;
+        }
+        // host=a,region=b
+        if let Some(tags) = env("INFLUX_TAGS") {
+            for tag in tags.split(',') {
+                match tag.split_once('=') {
+                    Some((key, value)) => {
+                        self.tags
+                            .insert(key.trim().to_string(), value.trim().to_string());
+                    }
+                    None => warn!("invalid tag {:?} in INFLUX_TAGS, expect key=value", tag),
+                }
+            }
+        }
+    }
+
+    /// 直播数据的客户端
+    pub fn client(&self) -> Result<InfluxSink> {
+        match &self.file {
+            Some(file) => InfluxSink::file(file),
+            None => self.client_of(&self.bucket),
+        }
+    }
+
+    /// 爬虫单独的客户端，没有设置 spider_bucket 或者写文件时为 None
+    pub fn spider_client(&self) -> Result<Option<InfluxSink>> {
+        if self.file.is_some() {
+            return Ok(None);
+        }
+        self.spider_bucket
+            .as_ref()
+            .map(|bucket| self.client_of(bucket))
+            .transpose()
+    }
+
+    fn client_of(&self, bucket: &str) -> Result<InfluxSink> {
+        info!(
+            "influx {:?}: {} org={} bucket={}",
+            self.api, self.url, self.org, bucket
+        );
+        match self.api {
+            InfluxApi::V1 => {
+                let auth = self
+                    .username
+                    .clone()
+                    .map(|username| (username, self.password.clone()));
+                InfluxSink::v1(&self.url, bucket, self.retention_policy.clone(), auth)
+            }
+            InfluxApi::V2 => {
+                let token = self
+                    .token
+                    .clone()
+                    .context("influx token is required, set INFLUX_TOKEN or token in the config")?;
+                let client = InfluxClient::new(self.url.clone(), token)?
+                    .with_org(self.org.clone())
+                    .with_bucket(bucket.to_string())
+                    .with_precision(Precision::MS);
+                Ok(InfluxSink::V2(client))
+            }
+        }
+    }
+
+    /// 设置 measurement 前缀和全局 tag，启动时设置一次
+    pub fn set_point_template(&self) {
+        let tags = self
+            .tags
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        *POINT_TEMPLATE.write() = (self.measurement_prefix.clone(), tags);
+    }
+}
+
+/// 新建数据点，加上 measurement 前缀和全局 tag
+pub fn new_point(measurement: &str) -> Point {
+    let template = POINT_TEMPLATE.read();
+    let (prefix, tags) = &*template;
+    let mut point = Point::new(format!("{}{}", prefix, measurement));
+    for (key, value) in tags {
+        point = point.tag(key.clone(), value.clone());
+    }
+    point
+}
@@ -21,6 +21,12 @@ pub struct DanmuCounter {
     danmu_counter: HashMap<i64, HashMap<u64, u32>>,
 }
 
+impl Default for DanmuCounter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl DanmuCounter {
     pub fn new() -> Self {
         Self {
@@ -0,0 +1,193 @@
+use chrono::{DateTime, Duration, Local};
+use influxdb_client::Point;
+
+use super::{
+    messages::{GuardEvent, GuardSource, ToPoint},
+    RoomInfo,
+};
+
+/// 同一次上舰的几条消息之间最多相隔多久（秒）
+const MERGE_WINDOW_SECS: i64 = 10;
+
+/// 合并完成的一次上舰
+#[derive(Debug)]
+pub struct MergedGuard {
+    pub room_id: u64,
+    /// 第一条消息的时间，也是写入的时间
+    pub time: DateTime<Local>,
+    /// 已经合并了哪些来源
+    pub sources: Vec<GuardSource>,
+    pub event: GuardEvent,
+}
+
+impl MergedGuard {
+    pub fn into_point(self) -> Point {
+        let room_info = RoomInfo::from_cache(self.room_id);
+        info!("舰长: {} @ {}", self.event, room_info.streamer);
+        self.event.into_point(&room_info, self.time)
+    }
+}
+
+/// 把 USER_TOAST_MSG、GUARD_BUY 和上舰的 SEND_GIFT 按 uid、舰队等级和时间合并为一次上舰
+///
+/// 消息在窗口内等待同一次上舰的其他消息，窗口按收到的 packet 时间推进，回放时也一样。
+/// 写入 influx、prometheus 和提醒都经过这里，只收到其中一种消息的上舰也会被统计。
+#[derive(Default)]
+pub struct GuardMerger {
+    pending: Vec<MergedGuard>,
+}
+
+impl GuardMerger {
+    pub fn push(&mut self, room_id: u64, event: GuardEvent, t: DateTime<Local>) {
+        let window = Duration::seconds(MERGE_WINDOW_SECS);
+        let same = self.pending.iter_mut().find(|p| {
+            p.room_id == room_id
+                && p.event.uid == event.uid
+                && p.event.guard_level == event.guard_level
+                && t - p.time <= window
+                // 同一来源的消息是另一次上舰
+                && !p.sources.contains(&event.source)
+        });
+        match same {
+            Some(pending) => {
+                debug!("merge guard {:?} of {}", event.source, event.username);
+                pending.sources.push(event.source);
+                pending.event.merge(event);
+            }
+            None => self.pending.push(MergedGuard {
+                room_id,
+                time: t,
+                sources: vec![event.source],
+                event,
+            }),
+        }
+    }
+
+    /// 取出等待超过窗口的上舰，now 为最近收到的 packet 时间
+    pub fn flush(&mut self, now: DateTime<Local>) -> Vec<MergedGuard> {
+        let window = Duration::seconds(MERGE_WINDOW_SECS);
+        let (finished, pending) = std::mem::take(&mut self.pending)
+            .into_iter()
+            .partition(|p| now - p.time > window);
+        self.pending = pending;
+        finished
+    }
+
+    /// 退出时取出所有上舰
+    pub fn flush_all(&mut self) -> Vec<MergedGuard> {
+        std::mem::take(&mut self.pending)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        influx::messages::{GuardBuy, SendGift, SendMsgReply, UserToastMsg},
+        revenue::Money,
+    };
+    use chrono::TimeZone;
+
+    const ROOM: u64 = 22637261;
+
+    fn fixture<T: serde::de::DeserializeOwned>(body: &str) -> T {
+        let msg: SendMsgReply = serde_json::from_str(body).unwrap();
+        serde_json::from_value(msg.data).unwrap()
+    }
+    fn toast() -> GuardEvent {
+        fixture::<UserToastMsg>(include_str!("../../fixtures/messages/user_toast_msg.json")).into()
+    }
+    fn guard_buy() -> GuardEvent {
+        fixture::<GuardBuy>(include_str!("../../fixtures/messages/guard_buy.json")).into()
+    }
+    fn send_gift() -> GuardEvent {
+        fixture::<SendGift>(include_str!("../../fixtures/messages/send_gift_guard.json"))
+            .guard_event()
+            .unwrap()
+    }
+    fn at(secs: i64) -> DateTime<Local> {
+        Local.timestamp(1624354023 + secs, 0)
+    }
+
+    #[test]
+    fn one_point_per_purchase() {
+        let mut merger = GuardMerger::default();
+        merger.push(ROOM, send_gift(), at(0));
+        merger.push(ROOM, guard_buy(), at(0));
+        merger.push(ROOM, toast(), at(1));
+        assert!(merger.flush(at(5)).is_empty());
+
+        let guards = merger.flush(at(11));
+        assert_eq!(guards.len(), 1);
+        assert_eq!(guards[0].sources.len(), 3);
+        assert_eq!(guards[0].time, at(0));
+        let points: Vec<Point> = guards.into_iter().map(MergedGuard::into_point).collect();
+        assert_eq!(points.len(), 1);
+        assert!(merger.flush_all().is_empty());
+    }
+
+    #[test]
+    fn most_accurate_price_wins() {
+        // USER_TOAST_MSG 的价格是实际支付的（续费有折扣），不论先后都以它为准
+        let mut merger = GuardMerger::default();
+        merger.push(ROOM, toast(), at(0));
+        merger.push(ROOM, guard_buy(), at(1));
+        merger.push(ROOM, send_gift(), at(2));
+        let guards = merger.flush_all();
+        assert_eq!(guards.len(), 1);
+        assert_eq!(guards[0].event.source, GuardSource::UserToast);
+        assert_eq!(guards[0].event.price, Money::from_gold(138000));
+        assert_eq!(guards[0].event.username, "鱼塘里的鸽子");
+
+        // 没有 USER_TOAST_MSG 时用 GUARD_BUY 的价格
+        merger.push(ROOM, guard_buy(), at(0));
+        merger.push(ROOM, send_gift(), at(1));
+        let guards = merger.flush_all();
+        assert_eq!(guards.len(), 1);
+        assert_eq!(guards[0].event.source, GuardSource::GuardBuy);
+        assert_eq!(guards[0].event.price, Money::from_gold(198000));
+    }
+
+    #[test]
+    fn same_source_is_another_purchase() {
+        let mut merger = GuardMerger::default();
+        merger.push(ROOM, guard_buy(), at(0));
+        merger.push(ROOM, guard_buy(), at(1));
+        merger.push(ROOM, toast(), at(2));
+        let guards = merger.flush_all();
+        assert_eq!(guards.len(), 2);
+        assert_eq!(
+            guards[0].sources,
+            vec![GuardSource::GuardBuy, GuardSource::UserToast]
+        );
+        assert_eq!(guards[1].sources, vec![GuardSource::GuardBuy]);
+    }
+
+    #[test]
+    fn other_room_is_another_purchase() {
+        let mut merger = GuardMerger::default();
+        merger.push(ROOM, guard_buy(), at(0));
+        merger.push(ROOM + 1, toast(), at(0));
+        assert_eq!(merger.flush_all().len(), 2);
+    }
+
+    #[test]
+    fn window_edge() {
+        let mut merger = GuardMerger::default();
+        merger.push(ROOM, guard_buy(), at(0));
+        // 正好在窗口上的消息还会合并
+        merger.push(ROOM, toast(), at(MERGE_WINDOW_SECS));
+        // 窗口外的是另一次上舰
+        merger.push(ROOM, send_gift(), at(MERGE_WINDOW_SECS + 1));
+
+        // 正好等满窗口时还不写入
+        assert!(merger.flush(at(MERGE_WINDOW_SECS)).is_empty());
+        let guards = merger.flush(at(MERGE_WINDOW_SECS + 1));
+        assert_eq!(guards.len(), 1);
+        assert_eq!(guards[0].event.source, GuardSource::UserToast);
+
+        let guards = merger.flush(at(2 * MERGE_WINDOW_SECS + 2));
+        assert_eq!(guards.len(), 1);
+        assert_eq!(guards[0].sources, vec![GuardSource::SendGift]);
+    }
+}
@@ -3,14 +3,23 @@ use std::time::Duration;
 use anyhow::{Context, Result};
 use biliapi::ws_protocol::{KnownOperation, Operation, Packet};
 use chrono::{DateTime, Local};
-use influxdb_client::Client as InfluxClient;
 use tokio::{
-    sync::broadcast::{self, error::RecvError},
+    sync::{
+        broadcast::{self, error::RecvError},
+        watch,
+    },
     time,
 };
 
-use super::{messages::*, CachedInfluxClient, DanmuCounter, RoomInfo};
-use crate::spider::SpiderInfo;
+use super::{
+    messages::*, CachedInfluxClient, ComboMerger, DanmuCounter, GuardMerger, InfluxSink,
+    KeywordCounter, RoomInfo, SuperChatCache,
+};
+use crate::{
+    keyword::{KeywordMatcher, KeywordRule},
+    revenue::Money,
+    spider::SpiderInfo,
+};
 
 const FLUSH_INTERVAL: Duration = Duration::from_secs(2);
 
@@ -19,31 +28,75 @@ const FLUSH_INTERVAL: Duration = Duration::from_secs(2);
 /// 提供一个 async_writer 选项，在此模式下会将写入放到后台执行，默认开启
 pub struct InfluxAppender {
     client: CachedInfluxClient,
+    /// 爬虫数据单独的 bucket，为 None 时和直播数据写在一起
+    spider_client: Option<CachedInfluxClient>,
     /// 接收直播的 packet
     packets_receiver: broadcast::Receiver<Packet>,
     /// 直播弹幕计数器
     danmu_counter: DanmuCounter,
     /// 爬虫信息接收
     spider_receiver: broadcast::Receiver<SpiderInfo>,
+    /// 关键词规则
+    keyword_rules: watch::Receiver<Vec<KeywordRule>>,
+    /// 关键词命中计数器
+    keyword_counter: KeywordCounter,
+    /// 最近的 SC，用于修正被删除的 SC
+    super_chats: SuperChatCache,
+    /// 合并同一次上舰的几条消息
+    guard_merger: GuardMerger,
+    /// 合并连击的礼物，为 None 时每个 SEND_GIFT 单独写入
+    combo_merger: Option<ComboMerger>,
+    /// 最近收到的 packet 的时间
+    latest_packet: Option<DateTime<Local>>,
 }
 
 impl InfluxAppender {
     pub fn new(
-        client: InfluxClient,
+        client: InfluxSink,
         packets_receiver: broadcast::Receiver<Packet>,
         spider_receiver: broadcast::Receiver<SpiderInfo>,
+        keyword_rules: watch::Receiver<Vec<KeywordRule>>,
     ) -> Self {
         let client = CachedInfluxClient::new(client);
+        let mut keyword_counter = KeywordCounter::default();
+        keyword_counter.set_matcher(KeywordMatcher::new(&keyword_rules.borrow()));
         Self {
             client,
+            spider_client: None,
             packets_receiver,
             danmu_counter: DanmuCounter::new(),
             spider_receiver,
+            keyword_rules,
+            keyword_counter,
+            super_chats: SuperChatCache::default(),
+            guard_merger: GuardMerger::default(),
+            combo_merger: None,
+            latest_packet: None,
         }
     }
 
     pub fn buffer_size(mut self, buffer_size: usize) -> Self {
         self.client = self.client.buffer_size(buffer_size);
+        self.spider_client = self
+            .spider_client
+            .map(|client| client.buffer_size(buffer_size));
+        self
+    }
+
+    /// 爬虫数据写入另一个客户端，buffer 大小和直播数据的相同
+    pub fn spider_client(mut self, client: InfluxSink) -> Self {
+        let buffer_size = self.client.get_buffer_size();
+        self.spider_client = Some(CachedInfluxClient::new(client).buffer_size(buffer_size));
+        self
+    }
+
+    /// 把一次连击的礼物合并为一个点
+    pub fn merge_combo(mut self, merge_combo: bool) -> Self {
+        self.combo_merger = if merge_combo {
+            Some(ComboMerger::default())
+        } else {
+            None
+        };
         self
     }
 
@@ -56,7 +109,21 @@ impl InfluxAppender {
     pub async fn start(mut self) -> Result<()> {
         self.start_writer().await;
         info!("the channel is closed. flushing remaining caches");
-        self.client.flush().await?;
+        for pt in self.keyword_counter.flush_all() {
+            self.client.insert_point(pt).await?;
+        }
+        for guard in self.guard_merger.flush_all() {
+            self.client.insert_point(guard.into_point()).await?;
+        }
+        if let Some(combo_merger) = &mut self.combo_merger {
+            for pt in combo_merger.flush_all() {
+                self.client.insert_point(pt).await?;
+            }
+        }
+        self.client.shutdown().await?;
+        if let Some(spider_client) = &mut self.spider_client {
+            spider_client.shutdown().await?;
+        }
         Ok(())
     }
 
@@ -68,9 +135,40 @@ impl InfluxAppender {
             // 最多等 2s，超时会检查尝试 flush
             tokio::select! {
                 _ = flush_interval.tick() => {
+                    if let Some(latest) = self.latest_packet {
+                        for pt in self.keyword_counter.flush(latest) {
+                            if let Err(e) = self.client.insert_point(pt).await {
+                                warn!("failed to insert keyword hits: {:?}", e);
+                            }
+                        }
+                        for guard in self.guard_merger.flush(latest) {
+                            if let Err(e) = self.client.insert_point(guard.into_point()).await {
+                                warn!("failed to insert guard: {:?}", e);
+                            }
+                        }
+                        let combos = self
+                            .combo_merger
+                            .as_mut()
+                            .map(|merger| merger.flush(latest))
+                            .unwrap_or_default();
+                        for pt in combos {
+                            if let Err(e) = self.client.insert_point(pt).await {
+                                warn!("failed to insert combo: {:?}", e);
+                            }
+                        }
+                    }
                     if let Err(e) = self.client.flush().await {
                         warn!("flush failed: {:?}", e);
                     }
+                    if let Some(spider_client) = &mut self.spider_client {
+                        if let Err(e) = spider_client.flush().await {
+                            warn!("flush spider points failed: {:?}", e);
+                        }
+                    }
+                },
+                Ok(()) = self.keyword_rules.changed() => {
+                    let matcher = KeywordMatcher::new(&self.keyword_rules.borrow());
+                    self.keyword_counter.set_matcher(matcher);
                 },
                 recv = self.packets_receiver.recv() => {
                     match recv {
@@ -118,6 +216,7 @@ impl InfluxAppender {
     async fn process_packet(&mut self, packet: Packet) -> Result<()> {
         let t = packet.time;
         let room_id = packet.room_id;
+        self.latest_packet = Some(t);
 
         match packet.operation {
             Operation::Known(KnownOperation::SendMsgReply) => {
@@ -141,7 +240,10 @@ impl InfluxAppender {
 
     async fn process_spider(&mut self, packet: SpiderInfo) -> Result<()> {
         let point = packet.into_point();
-        self.client.insert_point(point).await?;
+        match &mut self.spider_client {
+            Some(spider_client) => spider_client.insert_point(point).await?,
+            None => self.client.insert_point(point).await?,
+        }
         Ok(())
     }
 
@@ -158,8 +260,48 @@ impl InfluxAppender {
                 let sc: SuperChat =
                     serde_json::from_value(msg.data).context("convert msg to super chat failed")?;
                 info!("SC: {} @ {}", sc, room_info.streamer);
+                self.super_chats.insert(sc.clone());
                 sc.into_point(room_info, t)
             }
+            "SUPER_CHAT_MESSAGE_DELETE" => {
+                let delete: SuperChatDelete = serde_json::from_value(msg.data)
+                    .context("convert msg to SuperChatDelete failed")?;
+                let mut price = Money::ZERO;
+                for &id in &delete.ids {
+                    match self.super_chats.remove(id) {
+                        Some(sc) => {
+                            price += sc.price();
+                            self.client
+                                .insert_point(sc.into_deleted_point(room_info, t))
+                                .await?;
+                        }
+                        None => warn!("deleted SC {} not found @ {}", id, room_info.streamer),
+                    }
+                }
+                let moderation = Moderation::SuperChatDelete {
+                    ids: delete.ids,
+                    price,
+                };
+                info!("{} @ {}", moderation, room_info.streamer);
+                moderation.into_point(room_info, t)
+            }
+            "ROOM_BLOCK_MSG" => {
+                let block: RoomBlockMsg = serde_json::from_value(msg.data)
+                    .context("convert msg to RoomBlockMsg failed")?;
+                let moderation = Moderation::Block(block);
+                info!("{} @ {}", moderation, room_info.streamer);
+                moderation.into_point(room_info, t)
+            }
+            "WARNING" | "CUT_OFF" => {
+                let message = msg.msg.unwrap_or_default();
+                let moderation = if msg.cmd == "WARNING" {
+                    Moderation::Warning(message)
+                } else {
+                    Moderation::CutOff(message)
+                };
+                warn!("{} @ {}", moderation, room_info.streamer);
+                moderation.into_point(room_info, t)
+            }
             "SEND_GIFT" => {
                 let gift: SendGift =
                     serde_json::from_value(msg.data).context("convert msg to send gift failed")?;
@@ -167,18 +309,55 @@ impl InfluxAppender {
                 if gift.is_free() {
                     return Ok(());
                 }
+                if let Some(guard) = gift.guard_event() {
+                    self.guard_merger.push(room_info.id, guard, t);
+                    return Ok(());
+                }
+                let gift = match &mut self.combo_merger {
+                    Some(merger) => match merger.push(room_info.id, gift, t) {
+                        Some(gift) => gift,
+                        None => return Ok(()),
+                    },
+                    None => gift,
+                };
                 info!("礼物: {} @ {}", gift, room_info.streamer);
                 gift.into_point(room_info, t)
             }
+            "COMBO_END" => {
+                let merger = match &mut self.combo_merger {
+                    Some(merger) => merger,
+                    None => return Ok(()),
+                };
+                let end: ComboEnd = serde_json::from_value(msg.data)
+                    .context("convert msg to ComboEnd failed")?;
+                for pt in merger.end(room_info.id, &end) {
+                    self.client.insert_point(pt).await?;
+                }
+                return Ok(());
+            }
             "USER_TOAST_MSG" => {
                 let guard: UserToastMsg = serde_json::from_value(msg.data)
                     .context("convert msg to UserToastMsg failed.")?;
-                info!("舰长: {} @ {}", guard, room_info.streamer);
-                guard.into_point(room_info, t)
+                debug!("{} @ {}", guard, room_info.streamer);
+                self.guard_merger.push(room_info.id, guard.into(), t);
+                return Ok(());
+            }
+            "GUARD_BUY" => {
+                let guard: GuardBuy = serde_json::from_value(msg.data)
+                    .context("convert msg to GuardBuy failed.")?;
+                self.guard_merger.push(room_info.id, guard.into(), t);
+                return Ok(());
             }
             // 什么猪鼻名字
             // DANMU_MSG:4:0:2:2:2:0
             cmd if cmd.starts_with("DANMU_MSG") => {
+                // 关键词
+                if let Ok(danmu) = serde_json::from_value::<DanmuMsg>(serde_json::json!({
+                    "cmd": cmd,
+                    "info": msg.info,
+                })) {
+                    self.keyword_counter.count(room_info.id, &danmu.text, t);
+                }
                 // 统计弹幕，一秒打一次
                 self.danmu_counter.count(room_info.id, t);
                 for pt in self.danmu_counter.flush() {
@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Local, TimeZone};
+use influxdb_client::Point;
+
+use super::{
+    messages::{KeywordHits, ToPoint},
+    RoomInfo,
+};
+use crate::keyword::KeywordMatcher;
+
+/// 按分钟统计关键词命中次数
+#[derive(Default)]
+pub struct KeywordCounter {
+    matcher: KeywordMatcher,
+    /// 分钟的时间戳 => (房间, 关键词) => 次数
+    hits: HashMap<i64, HashMap<(u64, String), u32>>,
+}
+
+impl KeywordCounter {
+    pub fn set_matcher(&mut self, matcher: KeywordMatcher) {
+        self.matcher = matcher;
+    }
+
+    pub fn count(&mut self, room_id: u64, text: &str, t: DateTime<Local>) {
+        if self.matcher.is_empty() {
+            return;
+        }
+        let minute = t.timestamp() / 60 * 60;
+        for keyword in self.matcher.matches(room_id, text) {
+            debug!("keyword {} hit in room {}: {}", keyword, room_id, text);
+            *self
+                .hits
+                .entry(minute)
+                .or_default()
+                .entry((room_id, keyword.to_string()))
+                .or_default() += 1;
+        }
+    }
+
+    /// 取出已经结束的分钟，now 是最新 packet 的时间，回放时不会把没统计完的分钟写出去
+    pub fn flush(&mut self, now: DateTime<Local>) -> Vec<Point> {
+        let current = now.timestamp() / 60 * 60;
+        self.take(|minute| minute < current)
+    }
+
+    /// 退出或重放结束时取出所有分钟，包括还没结束的
+    pub fn flush_all(&mut self) -> Vec<Point> {
+        self.take(|_| true)
+    }
+
+    fn take(&mut self, finished: impl Fn(i64) -> bool) -> Vec<Point> {
+        let finished: Vec<i64> = self
+            .hits
+            .keys()
+            .filter(|&&minute| finished(minute))
+            .cloned()
+            .collect();
+        let mut points = vec![];
+        for minute in finished {
+            let t = Local.timestamp(minute, 0);
+            for ((room_id, keyword), hits) in self.hits.remove(&minute).unwrap_or_default() {
+                let room_info = RoomInfo::from_cache(room_id);
+                points.push(KeywordHits::new(keyword, hits).into_point(&room_info, t));
+            }
+        }
+        points
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keyword::KeywordRule;
+
+    const ROOM: u64 = 22637261;
+
+    fn at(secs: i64) -> DateTime<Local> {
+        Local.timestamp(1624354020 + secs, 0)
+    }
+
+    fn counter() -> KeywordCounter {
+        let mut counter = KeywordCounter::default();
+        counter.set_matcher(KeywordMatcher::new(&[KeywordRule {
+            name: None,
+            pattern: "草".to_string(),
+            regex: false,
+            rooms: vec![],
+        }]));
+        counter
+    }
+
+    fn hits(counter: &KeywordCounter, t: DateTime<Local>) -> u32 {
+        let minute = t.timestamp() / 60 * 60;
+        counter
+            .hits
+            .get(&minute)
+            .and_then(|hits| hits.get(&(ROOM, "草".to_string())))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn replay_only_flushes_finished_minutes() {
+        // 回放的时间远早于现在，一分钟统计到一半时 flush 不能把它写出去
+        let mut counter = counter();
+        counter.count(ROOM, "草", at(0));
+        counter.count(ROOM, "草草", at(10));
+        assert!(counter.flush(at(10)).is_empty());
+        counter.count(ROOM, "草", at(30));
+        assert_eq!(hits(&counter, at(0)), 3);
+
+        counter.count(ROOM, "草", at(61));
+        assert_eq!(counter.flush(at(61)).len(), 1);
+        assert_eq!(hits(&counter, at(0)), 0);
+        assert_eq!(hits(&counter, at(61)), 1);
+
+        assert_eq!(counter.flush_all().len(), 1);
+        assert!(counter.hits.is_empty());
+    }
+}
@@ -1,5 +1,7 @@
 use influxdb_client::Point;
 
+use crate::influx::config::{measurement, new_point};
+
 pub struct Danmu {
     value: u32,
 }
@@ -10,6 +12,6 @@ impl Danmu {
 }
 impl super::ToPoint for Danmu {
     fn into_basic_point(self) -> Point {
-        Point::new("live-popularity").field("danmu", self.value as i64)
+        new_point(measurement::POPULARITY).field("danmu", self.value as i64)
     }
 }
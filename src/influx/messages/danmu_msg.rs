@@ -1,4 +1,4 @@
-use anyhow::*;
+//! 弹幕的文本和发送者，ddpanel-cli 也会用到
 use chrono::{DateTime, NaiveDateTime, Utc};
 use serde::{de::Error as SerdeError, Deserialize, Serialize};
 use serde_json::Value;
@@ -44,7 +44,8 @@ impl<'de> Deserialize<'de> for DanmuMsg {
             ),
         }
         let body = Body::deserialize(deserializer)?;
-        if body.cmd != "DANMU_MSG" {
+        // 可能是 DANMU_MSG:4:0:2:2:2:0 这样的
+        if !body.cmd.starts_with("DANMU_MSG") {
             return Err(D::Error::custom("not danmu msg"));
         }
 
@@ -0,0 +1,137 @@
+use chrono::{DateTime, Local};
+use influxdb_client::Point;
+
+use crate::influx::config::{measurement, new_point};
+use std::fmt::{self, Display, Formatter};
+
+use super::{SendGift, UserToastMsg};
+use crate::revenue::Money;
+
+/// GUARD_BUY
+#[derive(Debug, Deserialize)]
+pub struct GuardBuy {
+    #[serde(deserialize_with = "super::u64_from_value")]
+    uid: u64,
+    username: String,
+    guard_level: u8,
+    num: u32,
+    /// 单价，金瓜子
+    #[serde(rename = "price")]
+    price_milli: u32,
+    gift_name: String,
+}
+
+/// 上舰消息的来源，同一次上舰三种消息可能都会收到。越靠后价格越准确
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum GuardSource {
+    SendGift,
+    GuardBuy,
+    UserToast,
+}
+
+/// 合并后的一次上舰
+#[derive(Debug, Clone)]
+pub struct GuardEvent {
+    pub source: GuardSource,
+    pub uid: u64,
+    pub username: String,
+    /// 1 总督，2 提督，3 舰长
+    pub guard_level: u8,
+    pub gift_name: String,
+    /// 月数
+    pub months: u32,
+    /// 每月的价格
+    pub unit_price: Money,
+    /// 总价
+    pub price: Money,
+}
+
+impl GuardEvent {
+    /// 同一次上舰的另一条消息，价格以更准确的来源为准
+    pub fn merge(&mut self, other: GuardEvent) {
+        if self.username.is_empty() {
+            self.username = other.username.clone();
+        }
+        if other.source > self.source {
+            let username = std::mem::take(&mut self.username);
+            *self = other;
+            self.username = username;
+        }
+    }
+}
+
+impl From<UserToastMsg> for GuardEvent {
+    fn from(toast: UserToastMsg) -> Self {
+        let months = toast.num().max(1);
+        Self {
+            source: GuardSource::UserToast,
+            uid: toast.sender_id(),
+            username: toast.sender_name().to_string(),
+            guard_level: toast.guard_level(),
+            gift_name: toast.gift_name().to_string(),
+            months,
+            unit_price: toast.price() / months,
+            price: toast.price(),
+        }
+    }
+}
+
+impl From<GuardBuy> for GuardEvent {
+    fn from(buy: GuardBuy) -> Self {
+        let unit_price = Money::from_gold(buy.price_milli as u64);
+        Self {
+            source: GuardSource::GuardBuy,
+            uid: buy.uid,
+            username: buy.username,
+            guard_level: buy.guard_level,
+            gift_name: buy.gift_name,
+            months: buy.num,
+            unit_price,
+            price: unit_price * buy.num,
+        }
+    }
+}
+
+impl SendGift {
+    /// 以礼物形式推送的上舰，其他礼物为 None
+    pub fn guard_event(&self) -> Option<GuardEvent> {
+        let guard_level = self.guard_level()?;
+        Some(GuardEvent {
+            source: GuardSource::SendGift,
+            uid: self.sender_id(),
+            username: self.sender_name().to_string(),
+            guard_level,
+            gift_name: self.gift_name().to_string(),
+            months: self.num(),
+            unit_price: self.value() / self.num(),
+            price: self.value(),
+        })
+    }
+}
+
+impl Display for GuardEvent {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_fmt(format_args!(
+            "Guard {{ {} * {} = {} }}",
+            self.gift_name, self.months, self.price
+        ))
+    }
+}
+impl super::ToPoint for GuardEvent {
+    fn into_point(self, room_info: &crate::influx::RoomInfo, t: DateTime<Local>) -> Point {
+        room_info
+            .tag_gift(self.into_basic_point(), room_info.id)
+            .timestamp(t.timestamp_millis())
+    }
+
+    fn into_basic_point(self) -> Point {
+        let pt = new_point(measurement::GIFT)
+            .tag("type", "guard")
+            .tag("gift_name", self.gift_name)
+            .tag("guard_level", self.guard_level.to_string())
+            .field("num", self.months as i64)
+            .field("unit_price", self.unit_price.yuan());
+        let pt = super::tag_sender(pt, self.uid, &self.username);
+        self.price.add_fields(pt)
+    }
+}
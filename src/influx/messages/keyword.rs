@@ -0,0 +1,21 @@
+use influxdb_client::Point;
+
+use crate::influx::config::{measurement, new_point};
+
+/// 一分钟内某个关键词命中的次数
+pub struct KeywordHits {
+    keyword: String,
+    hits: u32,
+}
+impl KeywordHits {
+    pub fn new(keyword: String, hits: u32) -> Self {
+        Self { keyword, hits }
+    }
+}
+impl super::ToPoint for KeywordHits {
+    fn into_basic_point(self) -> Point {
+        new_point(measurement::KEYWORD)
+            .tag("keyword", self.keyword)
+            .field("hits", self.hits as i64)
+    }
+}
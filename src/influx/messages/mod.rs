@@ -1,23 +1,65 @@
-use crate::influx::RoomInfo;
+use crate::influx::{
+    sender_schema::{SenderKeys, SenderSchema, SenderValue, SENDER_KEYS, USER_KEYS},
+    RoomInfo,
+};
 use chrono::{DateTime, Local};
 use influxdb_client::Point;
+use parking_lot::RwLock;
 use serde_json::Value;
 
+lazy_static::lazy_static! {
+    static ref SENDER_SCHEMA: RwLock<SenderSchema> = Default::default();
+}
+
+/// 设置 sender 的写入方式，启动时设置一次
+pub fn set_sender_schema(schema: SenderSchema) {
+    *SENDER_SCHEMA.write() = schema;
+}
+
+/// 按设置的 schema 写入送礼的人
+pub fn tag_sender(point: Point, uid: u64, name: &str) -> Point {
+    apply_sender_schema(point, SENDER_KEYS, uid, name)
+}
+
+/// 按设置的 schema 写入被处理的用户
+pub fn tag_user(point: Point, uid: u64, name: &str) -> Point {
+    apply_sender_schema(point, USER_KEYS, uid, name)
+}
+
+fn apply_sender_schema(mut point: Point, keys: SenderKeys, uid: u64, name: &str) -> Point {
+    let columns = SENDER_SCHEMA.read().columns(keys, uid, name);
+    for (key, value) in columns.tags {
+        point = point.tag(key, value);
+    }
+    for (key, value) in columns.fields {
+        point = match value {
+            SenderValue::Str(value) => point.field(key, value),
+            SenderValue::Int(value) => point.field(key, value),
+        };
+    }
+    point
+}
+
 /// SendMsgReply 对应的 message，大部分包都是这个类型
 #[derive(Debug, Deserialize)]
 pub struct SendMsgReply {
     pub cmd: String,
     #[serde(default = "Value::default")]
     pub data: Value,
+    /// 弹幕的内容在 info 里
+    #[serde(default = "Value::default")]
+    pub info: Value,
+    /// 警告、切断的原因在 msg 里
+    #[serde(default)]
+    pub msg: Option<String>,
 }
 
 pub trait ToPoint: Sized {
     fn into_basic_point(self) -> Point;
 
     fn into_point(self, room_info: &RoomInfo, t: DateTime<Local>) -> Point {
-        self.into_basic_point()
-            .tag("room_id", room_info.id.to_string())
-            .tag("streamer", room_info.streamer.as_str())
+        room_info
+            .tag_point(self.into_basic_point())
             .timestamp(t.timestamp_millis())
     }
 }
@@ -30,9 +72,8 @@ where
 
     let v = Value::deserialize(deserializer)?;
     match v {
-        Value::Null => {
-            unimplemented!()
-        }
+        // 和缺失时的 serde default 一样为 0
+        Value::Null => Ok(0),
         Value::Bool(b) => Err(Error::invalid_type(Unexpected::Bool(b), &"u64")),
         Value::Number(n) => match n.as_i64() {
             Some(n) => Ok(n as u64),
@@ -47,13 +88,21 @@ where
 }
 
 mod danmu;
+mod danmu_msg;
+mod guard;
+mod keyword;
+mod moderation;
 mod popularity;
 mod send_gift;
 mod super_chat;
 mod user_toast_msg;
 
 pub use danmu::Danmu;
+pub use danmu_msg::DanmuMsg;
+pub use guard::{GuardBuy, GuardEvent, GuardSource};
+pub use keyword::KeywordHits;
+pub use moderation::{Moderation, RoomBlockMsg, SuperChatDelete};
 pub use popularity::Popularity;
-pub use send_gift::SendGift;
+pub use send_gift::{ComboEnd, SendGift};
 pub use super_chat::SuperChat;
 pub use user_toast_msg::UserToastMsg;
@@ -0,0 +1,88 @@
+use influxdb_client::Point;
+
+use crate::influx::config::{measurement, new_point};
+use std::fmt::{self, Display, Formatter};
+
+use crate::revenue::Money;
+
+/// ROOM_BLOCK_MSG 的 data
+#[derive(Debug, Deserialize)]
+pub struct RoomBlockMsg {
+    #[serde(deserialize_with = "super::u64_from_value")]
+    uid: u64,
+    uname: String,
+    /// 1 房管，2 主播
+    #[serde(default)]
+    operator: u8,
+}
+
+/// SUPER_CHAT_MESSAGE_DELETE 的 data
+#[derive(Debug, Deserialize)]
+pub struct SuperChatDelete {
+    pub ids: Vec<u64>,
+}
+
+/// 禁言、警告、切断和删除 SC，写入 live-moderation
+#[derive(Debug)]
+pub enum Moderation {
+    Block(RoomBlockMsg),
+    /// 超管警告
+    Warning(String),
+    /// 直播被超管切断
+    CutOff(String),
+    /// 被删除的 SC，price 为其中找得到原 SC 的总价
+    SuperChatDelete {
+        ids: Vec<u64>,
+        price: Money,
+    },
+}
+impl Moderation {
+    fn kind(&self) -> &'static str {
+        match self {
+            Moderation::Block(_) => "block",
+            Moderation::Warning(_) => "warning",
+            Moderation::CutOff(_) => "cut_off",
+            Moderation::SuperChatDelete { .. } => "superchat_delete",
+        }
+    }
+}
+impl Display for Moderation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Moderation::Block(block) => f.write_fmt(format_args!(
+                "禁言 {{ {}({}) by {} }}",
+                block.uname, block.uid, block.operator
+            )),
+            Moderation::Warning(msg) => f.write_fmt(format_args!("警告 {{ {} }}", msg)),
+            Moderation::CutOff(msg) => f.write_fmt(format_args!("切断 {{ {} }}", msg)),
+            Moderation::SuperChatDelete { ids, price } => {
+                f.write_fmt(format_args!("删除 SC {{ {:?} {} }}", ids, price))
+            }
+        }
+    }
+}
+impl super::ToPoint for Moderation {
+    fn into_basic_point(self) -> Point {
+        let pt = new_point(measurement::MODERATION).tag("type", self.kind());
+        match self {
+            Moderation::Block(block) => {
+                let pt = pt
+                    .tag(
+                        "operator",
+                        match block.operator {
+                            2 => "anchor",
+                            _ => "admin",
+                        },
+                    )
+                    .field("count", 1.0);
+                super::tag_user(pt, block.uid, &block.uname)
+            }
+            Moderation::Warning(msg) | Moderation::CutOff(msg) => {
+                pt.field("message", msg).field("count", 1.0)
+            }
+            Moderation::SuperChatDelete { ids, price } => pt
+                .field("count", ids.len() as f64)
+                .field("price", price.yuan()),
+        }
+    }
+}
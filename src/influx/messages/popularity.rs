@@ -1,5 +1,7 @@
 use influxdb_client::Point;
 
+use crate::influx::config::{measurement, new_point};
+
 #[derive(Debug, Deserialize)]
 pub struct Popularity {
     pub value: i64,
@@ -11,6 +13,6 @@ impl Popularity {
 }
 impl super::ToPoint for Popularity {
     fn into_basic_point(self) -> Point {
-        Point::new("live-popularity").field("popularity", self.value)
+        new_point(measurement::POPULARITY).field("popularity", self.value)
     }
 }
@@ -3,12 +3,19 @@ use std::fmt::{self, Display, Formatter};
 
 use influxdb_client::Point;
 
+use crate::influx::config::{measurement, new_point};
+
+use crate::{influx::RoomInfo, revenue::Money};
+
 #[derive(Debug, Deserialize)]
 pub struct SendGift {
     coin_type: String,
     #[serde(rename = "giftName")]
     gift_name: String,
 
+    #[serde(default, rename = "giftId")]
+    gift_id: u64,
+
     // 瓜子
     #[serde(rename = "price")]
     price_milli: u32,
@@ -25,7 +32,43 @@ pub struct SendGift {
     /// 现在可以在某个直播间送给另一个主播礼物
     #[serde(default, rename = "send_master")]
     gift_receiver: Option<GiftReceiver>,
+
+    /// 盲盒爆出的礼物带有原来的盲盒
+    #[serde(default)]
+    blind_gift: Option<BlindGift>,
+
+    /// 连击的 id，不是连击时为空
+    #[serde(default)]
+    batch_combo_id: String,
+
+    /// 送出的时间（unix 秒）
+    #[serde(default)]
+    timestamp: i64,
+
+    /// 每次送礼不同的 id，同一秒送出的礼物也能区分
+    #[serde(default, deserialize_with = "super::u64_from_value")]
+    tid: u64,
+}
+/// COMBO_END，连击结束
+#[derive(Debug, Deserialize)]
+pub struct ComboEnd {
+    #[serde(deserialize_with = "super::u64_from_value")]
+    pub uid: u64,
+    /// 盲盒连击时为盲盒的名字，和爆出的礼物不同
+    pub gift_name: String,
+    /// 和连击中 SEND_GIFT 的 batch_combo_id 相同
+    #[serde(default)]
+    pub batch_combo_id: String,
 }
+/// 盲盒礼物的盲盒信息
+#[derive(Debug, Deserialize, Clone)]
+pub struct BlindGift {
+    /// 盲盒的名字
+    original_gift_name: String,
+    /// 盲盒的单价，瓜子
+    original_gift_price: u32,
+}
+
 /// 现在可以在某个直播间送给另一个主播礼物
 #[derive(Debug, Deserialize, Clone)]
 pub struct GiftReceiver {
@@ -35,26 +78,107 @@ pub struct GiftReceiver {
 }
 
 impl SendGift {
-    pub fn price(&self) -> f64 {
+    /// 送礼的人付出的价格，盲盒为盲盒的价格，也就是写入的 `price`
+    pub fn paid_price(&self) -> Money {
+        match &self.blind_gift {
+            Some(blind_gift) if self.coin_type == "gold" => {
+                Money::from_gold(blind_gift.original_gift_price as u64) * self.num
+            }
+            _ => self.value(),
+        }
+    }
+    /// 礼物本身的价值，盲盒为爆出的礼物
+    pub fn value(&self) -> Money {
         if self.coin_type == "gold" {
-            (self.price_milli * self.num) as f64 * 0.001
+            Money::from_gold(self.price_milli as u64) * self.num
+        } else {
+            Money::ZERO
+        }
+    }
+    /// 连麦时送给另一个直播间的主播，为对方的直播间
+    pub fn receiver_room_id(&self) -> Option<u64> {
+        self.gift_receiver
+            .as_ref()
+            .map(|receiver| receiver.room_id)
+            .filter(|&room_id| room_id != 0)
+    }
+    /// 收礼的直播间，优先用缓存里的名字
+    fn receiver_room(&self, room_info: &RoomInfo) -> RoomInfo {
+        match &self.gift_receiver {
+            Some(receiver)
+                if receiver.room_id != 0
+                    && receiver.room_id != room_info.id
+                    && Some(receiver.uid) != room_info.anchor_uid =>
+            {
+                RoomInfo::from_cache_opt(receiver.room_id)
+                    .or_else(|| RoomInfo::from_anchor_uid(receiver.uid))
+                    .unwrap_or_else(|| {
+                        let mut info = RoomInfo::new(receiver.room_id, receiver.uname.clone());
+                        info.anchor_uid = Some(receiver.uid);
+                        info
+                    })
+            }
+            _ => room_info.clone(),
+        }
+    }
+    /// 上舰的礼物对应的舰队等级
+    pub fn guard_level(&self) -> Option<u8> {
+        match self.gift_id {
+            10001 => Some(1),
+            10002 => Some(2),
+            10003 => Some(3),
+            _ => None,
+        }
+    }
+    pub fn sender_id(&self) -> u64 {
+        self.sender_id
+    }
+    pub fn timestamp(&self) -> i64 {
+        self.timestamp
+    }
+    pub fn tid(&self) -> u64 {
+        self.tid
+    }
+    pub fn num(&self) -> u32 {
+        self.num
+    }
+    pub fn combo_id(&self) -> Option<&str> {
+        if self.batch_combo_id.is_empty() {
+            None
         } else {
-            0f64
+            Some(&self.batch_combo_id)
         }
     }
+    /// 合并同一连击的同一种礼物
+    pub fn add_num(&mut self, other: &SendGift) {
+        self.num += other.num;
+    }
     pub fn is_free(&self) -> bool {
         self.coin_type == "silver"
     }
+    pub fn sender_name(&self) -> &str {
+        &self.sender_name
+    }
+    pub fn gift_name(&self) -> &str {
+        &self.gift_name
+    }
 }
 impl Display for SendGift {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         if self.coin_type == "gold" {
             f.write_fmt(format_args!(
-                "Gift {{ {} * {} = ￥{}",
+                "Gift {{ {} * {} = {}",
                 self.gift_name,
                 self.num,
-                self.price()
+                self.value()
             ))?;
+            if let Some(blind_gift) = &self.blind_gift {
+                f.write_fmt(format_args!(
+                    " ({} {})",
+                    blind_gift.original_gift_name,
+                    self.paid_price()
+                ))?;
+            }
             if let Some(receiver) = &self.gift_receiver {
                 f.write_fmt(format_args!(" => {}", receiver.uname))?;
             }
@@ -65,36 +189,36 @@ impl Display for SendGift {
     }
 }
 impl super::ToPoint for SendGift {
-    fn into_point(self, room_info: &crate::influx::RoomInfo, t: DateTime<Local>) -> Point {
-        let receiver = self.gift_receiver.clone();
-        let pt = self.into_basic_point();
-        match receiver {
-            Some(receiver) => pt
-                .tag("room_id", receiver.room_id.to_string())
-                .tag("streamer", receiver.uname),
-            None => pt
-                .tag("room_id", room_info.id.to_string())
-                .tag("streamer", room_info.streamer.as_str()),
-        }
-        .timestamp(t.timestamp_millis())
+    fn into_point(self, room_info: &RoomInfo, t: DateTime<Local>) -> Point {
+        let receiver = self.receiver_room(room_info);
+        receiver
+            .tag_gift(self.into_basic_point(), room_info.id)
+            .timestamp(t.timestamp_millis())
     }
 
     fn into_basic_point(self) -> Point {
         if !self.is_free() {
-            let price = self.price();
-            Point::new("live-gift")
+            let paid_price = self.paid_price();
+            let value = self.value();
+            let pt = new_point(measurement::GIFT)
                 .tag("type", "gift")
-                .tag("gift_name", self.gift_name)
-                .tag("sender", self.sender_id.to_string())
-                .tag("sender_name", self.sender_name.as_str())
-                .field("num", self.num as i64)
-                .field("price", price)
+                .tag("gift_name", self.gift_name);
+            let pt = super::tag_sender(pt, self.sender_id, &self.sender_name);
+            let pt = match self.blind_gift {
+                Some(blind_gift) => pt.tag("blind_box", blind_gift.original_gift_name),
+                None => pt,
+            }
+            .field("num", self.num as i64)
+            .field("paid_price", paid_price.yuan())
+            .field("value", value.yuan());
+            paid_price.add_fields(pt)
         } else {
-            Point::new("live-gift")
+            new_point(measurement::GIFT)
                 .tag("type", "free")
                 .tag("gift_name", self.gift_name)
                 .field("num", self.num as i64)
-                .field("coin", self.price_milli as f64)
+                .field("price", 0.0)
+                .field("silver", self.price_milli as i64 * self.num as i64)
         }
     }
 }
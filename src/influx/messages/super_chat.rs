@@ -1,14 +1,22 @@
+use chrono::{DateTime, Local};
 use influxdb_client::Point;
+
+use crate::influx::config::{measurement, new_point};
 use std::fmt::{self, Display, Formatter};
 
-#[derive(Debug, Deserialize)]
+use crate::revenue::Money;
+
+#[derive(Debug, Deserialize, Clone)]
 struct UserInfo {
     uname: String,
 }
 
 // super chat
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Clone)]
 pub struct SuperChat {
+    /// 删除 SC 时用这个 id
+    #[serde(default, deserialize_with = "super::u64_from_value")]
+    id: u64,
     // rmb
     price: u32,
     //
@@ -16,26 +24,63 @@ pub struct SuperChat {
     sender_id: u64,
 
     user_info: UserInfo,
+
+    #[serde(default)]
+    message: String,
 }
 impl SuperChat {
-    pub fn price(&self) -> f64 {
-        self.price as f64
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+    pub fn price(&self) -> Money {
+        Money::from_yuan(self.price as u64)
+    }
+    pub fn sender_name(&self) -> &str {
+        &self.user_info.uname
+    }
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// SC 被删除时的修正，价格和数量为负，加上 deleted tag 和原来的点区分
+    pub fn into_deleted_point(
+        self,
+        room_info: &crate::influx::RoomInfo,
+        t: DateTime<Local>,
+    ) -> Point {
+        room_info
+            .tag_gift(self.point(true), room_info.id)
+            .tag("deleted", "true")
+            .timestamp(t.timestamp_millis())
+    }
+
+    fn point(self, deleted: bool) -> Point {
+        let (price, num) = if deleted {
+            (-self.price(), -1)
+        } else {
+            (self.price(), 1)
+        };
+        let pt = new_point(measurement::GIFT)
+            .tag("type", "superchat")
+            .tag("gift_name", "superchat")
+            .field("num", num as i64);
+        let pt = super::tag_sender(pt, self.sender_id, &self.user_info.uname);
+        price.add_fields(pt)
     }
 }
 impl Display for SuperChat {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        f.write_fmt(format_args!("SuperChat {{ ￥{} }}", self.price))
+        f.write_fmt(format_args!("SuperChat {{ {} }}", self.price()))
     }
 }
 impl super::ToPoint for SuperChat {
+    fn into_point(self, room_info: &crate::influx::RoomInfo, t: DateTime<Local>) -> Point {
+        room_info
+            .tag_gift(self.into_basic_point(), room_info.id)
+            .timestamp(t.timestamp_millis())
+    }
+
     fn into_basic_point(self) -> Point {
-        let price = self.price();
-        Point::new("live-gift")
-            .tag("type", "superchat")
-            .tag("gift_name", "superchat")
-            .tag("sender", self.sender_id.to_string())
-            .tag("sender_name", self.user_info.uname)
-            .field("price", price)
-            .field("num", 1.0)
+        self.point(false)
     }
 }
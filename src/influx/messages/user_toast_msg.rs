@@ -1,6 +1,7 @@
-use influxdb_client::Point;
 use std::fmt::{self, Display, Formatter};
 
+use crate::revenue::Money;
+
 #[derive(Debug, Deserialize)]
 pub struct UserToastMsg {
     #[serde(rename = "uid")]
@@ -17,31 +18,38 @@ pub struct UserToastMsg {
     gift_name: String,
 
     num: u32,
+
+    /// 1 总督，2 提督，3 舰长
+    #[serde(default)]
+    guard_level: u8,
 }
 impl UserToastMsg {
-    pub fn price(&self) -> f64 {
-        (self.price_milli) as f64 * 0.001
+    pub fn sender_id(&self) -> u64 {
+        self.sender_id
+    }
+    pub fn num(&self) -> u32 {
+        self.num
+    }
+    pub fn price(&self) -> Money {
+        Money::from_gold(self.price_milli as u64)
+    }
+    pub fn guard_level(&self) -> u8 {
+        self.guard_level
+    }
+    pub fn sender_name(&self) -> &str {
+        &self.sender_name
+    }
+    pub fn gift_name(&self) -> &str {
+        &self.gift_name
     }
 }
 impl Display for UserToastMsg {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         f.write_fmt(format_args!(
-            "UserToastMsg {{ {} * {} = ￥{} }}",
+            "UserToastMsg {{ {} * {} = {} }}",
             self.gift_name,
             self.num,
             self.price()
         ))
     }
 }
-impl super::ToPoint for UserToastMsg {
-    fn into_basic_point(self) -> Point {
-        let price = self.price();
-        Point::new("live-gift")
-            .tag("type", "guard")
-            .tag("gift_name", self.gift_name)
-            .tag("sender", self.sender_id.to_string())
-            .tag("sender_name", self.sender_name.as_str())
-            .field("num", self.num as i64)
-            .field("price", price)
-    }
-}
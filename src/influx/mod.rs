@@ -1,44 +1,174 @@
-mod messages;
+pub mod messages;
 
 mod influx_appender;
 pub use influx_appender::InfluxAppender;
 mod cached_client;
+pub mod config;
+mod sink;
 pub use cached_client::CachedInfluxClient;
+pub use sink::InfluxSink;
 mod danmu_counter;
 pub use danmu_counter::DanmuCounter;
+mod combo_merger;
+pub use combo_merger::ComboMerger;
+mod guard_merger;
+pub use guard_merger::{GuardMerger, MergedGuard};
+mod keyword_counter;
+pub mod sender_schema;
+pub use keyword_counter::KeywordCounter;
+mod super_chat_cache;
+pub use super_chat_cache::SuperChatCache;
+
+use influxdb_client::Point;
+
+use crate::task_factory::RoomOptions;
 
 /// influx 最好有额外的信息（房间名）
+#[derive(Debug, Clone)]
 pub struct RoomInfo {
     pub id: u64,
+    /// watch.toml 里写的房间号，可能是短号
+    pub watch_id: u64,
     pub streamer: String,
+    /// 主播的 uid，回放时未知
+    pub anchor_uid: Option<u64>,
+    /// watch.toml 里的房间设置
+    pub options: RoomOptions,
+    /// 爬虫拿到的当前标题和分区
+    pub activity: Option<RoomActivity>,
+    /// 还在监控列表里。移除后缓存保留，还没写完的数据仍然有房间的 tag
+    pub watched: bool,
+}
+
+/// 直播间当前在做什么，用来区分唱歌、游戏等
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RoomActivity {
+    pub title: String,
+    pub area: String,
+    pub parent_area: String,
 }
 impl RoomInfo {
+    pub fn new(id: u64, streamer: String) -> Self {
+        Self {
+            id,
+            watch_id: id,
+            streamer,
+            anchor_uid: None,
+            options: RoomOptions::default(),
+            activity: None,
+            watched: true,
+        }
+    }
+
     #[inline(always)]
     pub fn from_cache_opt(id: u64) -> Option<Self> {
-        crate::manager::ROOM_ID_TO_STREAMER
-            .read()
-            .get(&id)
-            .cloned()
-            .map(|streamer| RoomInfo { id, streamer })
+        crate::manager::ROOM_INFOS.read().get(&id).cloned()
     }
 
     #[inline(always)]
     pub fn from_cache(id: u64) -> Self {
         Self::from_cache_opt(id).unwrap_or_else(|| {
             warn!("room {} streamer name not found! This is a logic flaw!", id);
-            Self {
-                id,
-                streamer: id.to_string(),
-            }
+            Self::new(id, id.to_string())
         })
     }
-    pub fn write_cache(id: u64, streamer: String) {
-        crate::manager::ROOM_ID_TO_STREAMER
-            .write()
-            .insert(id, streamer.clone());
+    pub fn write_cache(mut info: RoomInfo) {
         debug!(
             "wrote streamer name = {} to cache (room = {})",
-            streamer, id
+            info.streamer, info.id
         );
+        let mut infos = crate::manager::ROOM_INFOS.write();
+        // 重连时保留爬虫拿到的标题和分区
+        if info.activity.is_none() {
+            info.activity = infos.get(&info.id).and_then(|old| old.activity.clone());
+        }
+        infos.insert(info.id, info);
+    }
+
+    /// 移出监控列表后不再爬这个主播
+    pub fn unwatch(watch_id: u64) {
+        for (id, info) in crate::manager::ROOM_INFOS.write().iter_mut() {
+            if info.watch_id == watch_id && info.watched {
+                debug!("room {} (watch id = {}) is no longer watched", id, watch_id);
+                info.watched = false;
+            }
+        }
+    }
+
+    /// 更新已缓存房间的标题和分区
+    pub fn write_activity(id: u64, activity: RoomActivity) {
+        if let Some(info) = crate::manager::ROOM_INFOS.write().get_mut(&id) {
+            if info.activity.as_ref() != Some(&activity) {
+                debug!("room {} activity changed: {:?}", id, activity);
+                info.activity = Some(activity);
+            }
+        }
+    }
+
+    /// 更新已缓存房间的设置
+    pub fn write_options(id: u64, options: RoomOptions) {
+        if let Some(info) = crate::manager::ROOM_INFOS.write().get_mut(&id) {
+            info.options = options;
+        }
+    }
+
+    /// 房间的设置，不需要 clone 整个 RoomInfo
+    pub fn options_of<T>(id: u64, f: impl FnOnce(&RoomOptions) -> T) -> T {
+        match crate::manager::ROOM_INFOS.read().get(&id) {
+            Some(info) => f(&info.options),
+            None => f(&RoomOptions::default()),
+        }
+    }
+
+    /// 需要爬 up 主信息的主播 uid
+    pub fn spider_uploaders() -> Vec<u64> {
+        crate::manager::ROOM_INFOS
+            .read()
+            .values()
+            .filter(|info| info.watched && info.options.spider_uploader)
+            .filter_map(|info| info.anchor_uid)
+            .collect()
+    }
+
+    /// 由主播 uid 找到对应的直播间
+    pub fn from_anchor_uid(uid: u64) -> Option<Self> {
+        crate::manager::ROOM_INFOS
+            .read()
+            .values()
+            .find(|info| info.anchor_uid == Some(uid))
+            .cloned()
+    }
+
+    /// 给数据点打上房间相关的 tag
+    pub fn tag_point(&self, point: Point) -> Point {
+        let mut point = point
+            .tag("room_id", self.id.to_string())
+            .tag("streamer", self.streamer.as_str());
+        if let Some(alias) = &self.options.alias {
+            point = point.tag("alias", alias.as_str());
+        }
+        if let Some(group) = &self.options.group {
+            point = point.tag("group", group.as_str());
+        }
+        point
+    }
+
+    /// 礼物、SC、上舰的点：self 为收礼的直播间，origin_room_id 为送出礼物的直播间，
+    /// 连麦时给对方主播送礼两者不同
+    pub fn tag_gift(&self, point: Point, origin_room_id: u64) -> Point {
+        self.tag_activity(self.tag_point(point))
+            .tag("origin_room_id", origin_room_id.to_string())
+            .tag("receiver_room_id", self.id.to_string())
+    }
+
+    /// 给数据点打上当前标题和分区的 tag
+    pub fn tag_activity(&self, point: Point) -> Point {
+        match &self.activity {
+            Some(activity) => point
+                .tag("title", activity.title.as_str())
+                .tag("area", activity.area.as_str())
+                .tag("parent_area", activity.parent_area.as_str()),
+            None => point,
+        }
     }
 }
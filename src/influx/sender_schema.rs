@@ -0,0 +1,149 @@
+//! 送礼的人（sender）怎样写入 influx
+//!
+//! 每个观众一个 tag 会让 series 数量一直增长。这里决定 uid 和用户名写成 tag、field、
+//! 哈希、分桶还是不写。`ddpanel-cli rewrite-schema` 重放录制的文件，按新的规则重新写出。
+//!
+//! 同一个 measurement 里一个 key 不能既是 tag 又是 field，所以写成 field 时用另外的 key。
+
+use std::str::FromStr;
+
+use anyhow::{bail, Context};
+
+/// 默认的分桶数
+const DEFAULT_BUCKETS: u32 = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SenderSchema {
+    /// uid 和用户名都是 tag，和以前一样
+    Tag,
+    /// uid 和用户名都是 field
+    Field,
+    /// 只写 uid 的哈希，为 field，不写用户名
+    Hash,
+    /// uid 的哈希分桶为 tag，uid 和用户名为 field
+    Bucket(u32),
+    /// 不写
+    Drop,
+}
+
+impl Default for SenderSchema {
+    fn default() -> Self {
+        SenderSchema::Tag
+    }
+}
+
+impl FromStr for SenderSchema {
+    type Err = anyhow::Error;
+
+    /// tag、field、hash、bucket、bucket:N 或 drop
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tag" => Ok(SenderSchema::Tag),
+            "field" => Ok(SenderSchema::Field),
+            "hash" => Ok(SenderSchema::Hash),
+            "bucket" => Ok(SenderSchema::Bucket(DEFAULT_BUCKETS)),
+            "drop" => Ok(SenderSchema::Drop),
+            s if s.starts_with("bucket:") => {
+                let buckets: u32 = s["bucket:".len()..]
+                    .parse()
+                    .with_context(|| format!("invalid bucket count in {:?}", s))?;
+                if buckets == 0 {
+                    bail!("bucket count must be positive");
+                }
+                Ok(SenderSchema::Bucket(buckets))
+            }
+            _ => bail!(
+                "unknown sender schema {:?}, expect tag, field, hash, bucket[:N] or drop",
+                s
+            ),
+        }
+    }
+}
+
+/// 一组 sender 的 key，礼物为 sender，禁言为 user
+#[derive(Debug, Clone, Copy)]
+pub struct SenderKeys {
+    /// uid 的 tag
+    pub id: &'static str,
+    /// 用户名的 tag
+    pub name: &'static str,
+    /// uid 的 field
+    pub id_field: &'static str,
+    /// 用户名的 field
+    pub name_field: &'static str,
+    pub hash: &'static str,
+    pub bucket: &'static str,
+}
+
+pub const SENDER_KEYS: SenderKeys = SenderKeys {
+    id: "sender",
+    name: "sender_name",
+    id_field: "sender_uid",
+    name_field: "sender_uname",
+    hash: "sender_hash",
+    bucket: "sender_bucket",
+};
+
+pub const USER_KEYS: SenderKeys = SenderKeys {
+    id: "user",
+    name: "user_name",
+    id_field: "user_uid",
+    name_field: "user_uname",
+    hash: "user_hash",
+    bucket: "user_bucket",
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SenderValue {
+    Str(String),
+    Int(i64),
+}
+
+/// 按 schema 拆分后的 tag 和 field
+#[derive(Debug, Default)]
+pub struct SenderColumns {
+    pub tags: Vec<(&'static str, String)>,
+    pub fields: Vec<(&'static str, SenderValue)>,
+}
+
+impl SenderSchema {
+    pub fn columns(self, keys: SenderKeys, uid: u64, name: &str) -> SenderColumns {
+        let mut columns = SenderColumns::default();
+        let uid_value = || SenderValue::Int(uid as i64);
+        match self {
+            SenderSchema::Tag => {
+                columns.tags.push((keys.id, uid.to_string()));
+                columns.tags.push((keys.name, name.to_string()));
+            }
+            SenderSchema::Field => {
+                columns.fields.push((keys.id_field, uid_value()));
+                columns
+                    .fields
+                    .push((keys.name_field, SenderValue::Str(name.to_string())));
+            }
+            SenderSchema::Hash => {
+                columns.fields.push((
+                    keys.hash,
+                    SenderValue::Str(format!("{:016x}", fnv1a(&uid.to_string()))),
+                ));
+            }
+            SenderSchema::Bucket(buckets) => {
+                let bucket = fnv1a(&uid.to_string()) % buckets as u64;
+                columns.tags.push((keys.bucket, bucket.to_string()));
+                columns.fields.push((keys.id_field, uid_value()));
+                columns
+                    .fields
+                    .push((keys.name_field, SenderValue::Str(name.to_string())));
+            }
+            SenderSchema::Drop => {}
+        }
+        columns
+    }
+}
+
+/// 固定的哈希，不随 rust 版本变化。哈希 uid 的十进制字符串
+fn fnv1a(s: &str) -> u64 {
+    s.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, b| {
+        (hash ^ b as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
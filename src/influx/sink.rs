@@ -0,0 +1,154 @@
+//! 数据点写到哪里：influxdb 2.x、1.x 的 `/write` 接口，或者离线的 gzip line protocol 文件
+
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::{bail, Context, Result};
+use async_compression::tokio::write::GzipEncoder;
+use influxdb_client::{Client as InfluxClient, Point, TimestampOptions};
+use tokio::{
+    fs::File,
+    io::{AsyncWriteExt, BufWriter},
+    sync::Mutex,
+};
+
+/// 1.x 写入的超时，卡住的请求会一直占着 flush
+const V1_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Clone)]
+pub enum InfluxSink {
+    /// influxdb 2.x，`/api/v2/write`
+    V2(InfluxClient),
+    /// influxdb 1.x 或 VictoriaMetrics，`/write?db=`
+    V1(Arc<V1Writer>),
+    /// 不连接数据库，写入 gzip 的 line protocol 文件
+    File(Arc<LpFile>),
+}
+
+pub struct V1Writer {
+    client: reqwest::Client,
+    url: String,
+    database: String,
+    retention_policy: Option<String>,
+    auth: Option<(String, Option<String>)>,
+}
+
+pub struct LpFile {
+    path: PathBuf,
+    encoder: Mutex<GzipEncoder<BufWriter<File>>>,
+}
+
+impl InfluxSink {
+    pub fn v1(
+        url: &str,
+        database: &str,
+        retention_policy: Option<String>,
+        auth: Option<(String, Option<String>)>,
+    ) -> Result<Self> {
+        Ok(InfluxSink::V1(Arc::new(V1Writer {
+            client: reqwest::Client::builder().timeout(V1_TIMEOUT).build()?,
+            url: format!("{}/write", url.trim_end_matches('/')),
+            database: database.to_string(),
+            retention_policy,
+            auth,
+        })))
+    }
+
+    pub fn file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file =
+            std::fs::File::create(&path).with_context(|| format!("failed to create {:?}", path))?;
+        info!("writing influx line protocol to {:?}", path);
+        let writer = BufWriter::new(File::from_std(file));
+        Ok(InfluxSink::File(Arc::new(LpFile {
+            path,
+            encoder: Mutex::new(GzipEncoder::new(writer)),
+        })))
+    }
+
+    /// 写文件时按顺序同步写入
+    pub fn is_file(&self) -> bool {
+        matches!(self, InfluxSink::File(_))
+    }
+
+    pub async fn write(&self, points: &[Point]) -> Result<()> {
+        match self {
+            InfluxSink::V2(client) => {
+                client
+                    .insert_points(points, TimestampOptions::FromPoint)
+                    .await?;
+            }
+            InfluxSink::V1(writer) => writer.write(points).await?,
+            InfluxSink::File(file) => file.write(points).await?,
+        }
+        Ok(())
+    }
+
+    /// 写完 gzip 的结尾，之后不能再写入。数据库不需要
+    pub async fn shutdown(&self) -> Result<()> {
+        if let InfluxSink::File(file) = self {
+            file.shutdown().await?;
+        }
+        Ok(())
+    }
+}
+
+/// 时间戳都是毫秒
+fn line_protocol(points: &[Point]) -> String {
+    let mut body = String::new();
+    for point in points {
+        body.push_str(&point.serialize());
+        body.push('\n');
+    }
+    body
+}
+
+impl V1Writer {
+    async fn write(&self, points: &[Point]) -> Result<()> {
+        let mut query = vec![("db", self.database.as_str()), ("precision", "ms")];
+        if let Some(rp) = &self.retention_policy {
+            query.push(("rp", rp.as_str()));
+        }
+        let mut request = self
+            .client
+            .post(&self.url)
+            .query(&query)
+            .body(line_protocol(points));
+        if let Some((username, password)) = &self.auth {
+            request = request.basic_auth(username, password.as_ref());
+        }
+        let response = request.send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            bail!("influx v1 write failed with {}: {}", status, body);
+        }
+        Ok(())
+    }
+}
+
+impl LpFile {
+    async fn write(&self, points: &[Point]) -> Result<()> {
+        let mut encoder = self.encoder.lock().await;
+        encoder
+            .write_all(line_protocol(points).as_bytes())
+            .await
+            .with_context(|| format!("failed to write {:?}", self.path))?;
+        encoder
+            .flush()
+            .await
+            .with_context(|| format!("failed to write {:?}", self.path))
+    }
+
+    async fn shutdown(&self) -> Result<()> {
+        self.encoder
+            .lock()
+            .await
+            .shutdown()
+            .await
+            .with_context(|| format!("failed to finish {:?}", self.path))
+    }
+}
@@ -0,0 +1,37 @@
+use std::collections::{HashMap, VecDeque};
+
+use super::messages::SuperChat;
+
+/// 最多记住多少条 SC
+const CAPACITY: usize = 4096;
+
+/// 最近的 SC，删除 SC 时按 id 找回原来的 SC
+#[derive(Default)]
+pub struct SuperChatCache {
+    chats: HashMap<u64, SuperChat>,
+    /// 插入顺序，超出容量时先移除最早的
+    order: VecDeque<u64>,
+}
+
+impl SuperChatCache {
+    pub fn insert(&mut self, sc: SuperChat) {
+        let id = sc.id();
+        // 没有 id 的 SC 无法被匹配
+        if id == 0 || self.chats.insert(id, sc).is_some() {
+            return;
+        }
+        self.order.push_back(id);
+        while self.order.len() > CAPACITY {
+            if let Some(id) = self.order.pop_front() {
+                self.chats.remove(&id);
+            }
+        }
+    }
+
+    /// 取出被删除的 SC，找不到时为 None
+    pub fn remove(&mut self, id: u64) -> Option<SuperChat> {
+        let sc = self.chats.remove(&id)?;
+        self.order.retain(|&i| i != id);
+        Some(sc)
+    }
+}
@@ -0,0 +1,94 @@
+//! 弹幕关键词匹配
+//!
+//! 规则写在 watch.toml 的 `[[keyword]]` 里，随任务文件重新加载。
+//! influx 按分钟统计命中次数，alert 可以对命中发提醒。
+
+use regex::Regex;
+
+use crate::influx::RoomInfo;
+
+/// watch.toml 里的一条 `[[keyword]]`
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub struct KeywordRule {
+    /// 写入 influx 的 keyword tag，默认为 pattern
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub pattern: String,
+    /// pattern 是否为正则，默认为普通的子串
+    #[serde(default)]
+    pub regex: bool,
+    /// 只匹配这些直播间，为空时匹配所有直播间
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rooms: Vec<u64>,
+}
+
+enum Pattern {
+    Plain(String),
+    Regex(Regex),
+}
+
+struct Compiled {
+    name: String,
+    pattern: Pattern,
+    rooms: Vec<u64>,
+}
+
+#[derive(Default)]
+pub struct KeywordMatcher {
+    rules: Vec<Compiled>,
+}
+
+impl KeywordMatcher {
+    /// 编译规则，错误的正则会被跳过
+    pub fn new(rules: &[KeywordRule]) -> Self {
+        let rules = rules
+            .iter()
+            .filter_map(|rule| {
+                let pattern = if rule.regex {
+                    match Regex::new(&rule.pattern) {
+                        Ok(re) => Pattern::Regex(re),
+                        Err(e) => {
+                            warn!("invalid keyword regex {:?}: {}", rule.pattern, e);
+                            return None;
+                        }
+                    }
+                } else {
+                    Pattern::Plain(rule.pattern.clone())
+                };
+                Some(Compiled {
+                    name: rule.name.clone().unwrap_or_else(|| rule.pattern.clone()),
+                    pattern,
+                    rooms: rule.rooms.clone(),
+                })
+            })
+            .collect();
+        Self { rules }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// 命中的关键词名
+    pub fn matches(&self, room_id: u64, text: &str) -> Vec<&str> {
+        // rooms 里可能写的是短号
+        let watch_id = if self.rules.iter().any(|rule| !rule.rooms.is_empty()) {
+            RoomInfo::from_cache_opt(room_id).map(|info| info.watch_id)
+        } else {
+            None
+        };
+        self.rules
+            .iter()
+            .filter(|rule| {
+                rule.rooms.is_empty()
+                    || rule.rooms.contains(&room_id)
+                    || watch_id.map(|id| rule.rooms.contains(&id)).unwrap_or(false)
+            })
+            .filter(|rule| match &rule.pattern {
+                Pattern::Plain(s) => text.contains(s.as_str()),
+                Pattern::Regex(re) => re.is_match(text),
+            })
+            .map(|rule| rule.name.as_str())
+            .collect()
+    }
+}
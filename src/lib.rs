@@ -0,0 +1,25 @@
+//! ddpanel 的各个组件，`ddpanel` 和 `ddpanel-cli` 共用
+#[macro_use]
+extern crate serde;
+#[macro_use]
+extern crate log;
+
+pub mod admin;
+pub mod alert;
+pub mod discovery;
+pub mod file_appender;
+pub mod http;
+pub mod influx;
+pub mod keyword;
+pub mod live_connection;
+pub mod live_status;
+pub mod manager;
+pub mod monitor;
+pub mod prometheus;
+pub mod replayer;
+pub mod requests;
+pub mod revenue;
+pub mod spider;
+pub mod task_factory;
+#[cfg(test)]
+mod test_util;
@@ -0,0 +1,267 @@
+//! 带登录信息的弹幕连接
+//!
+//! biliapi 的 `LiveConnection` 认证时 uid 为 0，匿名连接收到的弹幕里用户名和 uid 会被隐藏。
+//! 这里用爬虫的 cookie 里的 uid 和 buvid 认证，收到的包转换为 biliapi 的 [`Packet`]，
+//! 下游不需要区分。
+
+use std::{
+    io::Read,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use async_tungstenite::{tokio::connect_async, tungstenite::Message};
+use biliapi::ws_protocol::Packet;
+use chrono::Local;
+use futures::{channel::mpsc, stream::BoxStream, SinkExt, StreamExt};
+use reqwest_cookie_store::CookieStoreMutex;
+
+/// 心跳间隔
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+/// 多久没有收到任何包认为连接已断开
+const READ_TIMEOUT: Duration = Duration::from_secs(90);
+const HEADER_LEN: usize = 16;
+
+// 协议版本
+const PROTOVER_JSON: u16 = 0;
+const PROTOVER_INT: u16 = 1;
+const PROTOVER_ZLIB: u16 = 2;
+
+// 操作
+const OP_HEARTBEAT: u32 = 2;
+const OP_HEARTBEAT_REPLY: u32 = 3;
+const OP_SEND_MSG_REPLY: u32 = 5;
+const OP_AUTH: u32 = 7;
+const OP_AUTH_REPLY: u32 = 8;
+
+/// 认证时带上的用户信息
+#[derive(Debug, Clone)]
+pub struct LiveAuth {
+    pub uid: u64,
+    pub buvid: String,
+}
+
+impl LiveAuth {
+    /// 从 cookie 里取出 uid 和 buvid，未登录时为 None
+    pub fn from_cookies(cookies: &CookieStoreMutex) -> Option<Self> {
+        let cookies = cookies.lock().ok()?;
+        let uid = cookies
+            .get("bilibili.com", "/", "DedeUserID")?
+            .value()
+            .parse()
+            .ok()?;
+        let buvid = cookies
+            .get("bilibili.com", "/", "buvid3")
+            .map(|c| c.value().to_string())
+            .unwrap_or_default();
+        Some(Self { uid, buvid })
+    }
+}
+
+/// 连接并认证，返回收到的包
+pub async fn connect(
+    url: &str,
+    room_id: u64,
+    token: String,
+    auth: &LiveAuth,
+) -> Result<BoxStream<'static, Result<Packet>>> {
+    let (mut ws, _) = connect_async(url).await?;
+
+    let auth_body = serde_json::json!({
+        "uid": auth.uid,
+        "roomid": room_id,
+        "protover": PROTOVER_ZLIB,
+        "buvid": auth.buvid,
+        "platform": "web",
+        "type": 2,
+        "key": token,
+    });
+    ws.send(Message::Binary(encode(
+        OP_AUTH,
+        auth_body.to_string().as_bytes(),
+    )))
+    .await?;
+
+    let (packets_tx, packets_rx) = mpsc::unbounded();
+    tokio::spawn(async move {
+        let mut heartbeat = tokio::time::interval(HEARTBEAT_INTERVAL);
+        let mut last_read = Instant::now();
+        loop {
+            let ret: Result<()> = tokio::select! {
+                _ = heartbeat.tick() => {
+                    if last_read.elapsed() > READ_TIMEOUT {
+                        Err(anyhow!("no packet received in {:?}", READ_TIMEOUT))
+                    } else {
+                        ws.send(Message::Binary(encode(OP_HEARTBEAT, b"[object Object]")))
+                            .await
+                            .map_err(Into::into)
+                    }
+                }
+                msg = ws.next() => match msg {
+                    Some(Ok(Message::Binary(data))) => {
+                        last_read = Instant::now();
+                        decode(&data, room_id).map(|packets| {
+                            for packet in packets {
+                                // 接收端关闭时在下面退出
+                                let _ = packets_tx.unbounded_send(Ok(packet));
+                            }
+                        })
+                    }
+                    Some(Ok(Message::Close(frame))) => Err(anyhow!("connection closed: {:?}", frame)),
+                    Some(Ok(_)) => Ok(()),
+                    Some(Err(e)) => Err(e.into()),
+                    None => Err(anyhow!("connection ran out")),
+                },
+            };
+            if let Err(e) = ret {
+                let _ = packets_tx.unbounded_send(Err(e));
+                return;
+            }
+            if packets_tx.is_closed() {
+                debug!("authed connection of room {} dropped", room_id);
+                return;
+            }
+        }
+    });
+    Ok(packets_rx.boxed())
+}
+
+fn encode(operation: u32, body: &[u8]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(HEADER_LEN + body.len());
+    buf.extend_from_slice(&((HEADER_LEN + body.len()) as u32).to_be_bytes());
+    buf.extend_from_slice(&(HEADER_LEN as u16).to_be_bytes());
+    buf.extend_from_slice(&PROTOVER_INT.to_be_bytes());
+    buf.extend_from_slice(&operation.to_be_bytes());
+    buf.extend_from_slice(&1u32.to_be_bytes());
+    buf.extend_from_slice(body);
+    buf
+}
+
+/// 解析一个 websocket 消息，可能包含多个包，压缩的包会展开
+fn decode(mut data: &[u8], room_id: u64) -> Result<Vec<Packet>> {
+    let mut packets = vec![];
+    while data.len() >= HEADER_LEN {
+        let total_len = u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as usize;
+        let header_len = u16::from_be_bytes([data[4], data[5]]) as usize;
+        let protover = u16::from_be_bytes([data[6], data[7]]);
+        let operation = u32::from_be_bytes([data[8], data[9], data[10], data[11]]);
+        // 长度为 0 时 data 不会前进，会一直循环下去
+        if header_len < HEADER_LEN || total_len < header_len || total_len > data.len() {
+            bail!("invalid packet length {} of {}", total_len, data.len());
+        }
+        let body = &data[header_len..total_len];
+        data = &data[total_len..];
+
+        match (operation, protover) {
+            (OP_SEND_MSG_REPLY, PROTOVER_ZLIB) => {
+                let mut buf = vec![];
+                flate2::read::ZlibDecoder::new(body)
+                    .read_to_end(&mut buf)
+                    .context("failed to decompress packet")?;
+                packets.extend(decode(&buf, room_id)?);
+            }
+            (OP_SEND_MSG_REPLY, PROTOVER_JSON) => {
+                let body = String::from_utf8_lossy(body);
+                packets.push(packet("SendMsgReply", &body, room_id)?);
+            }
+            (OP_HEARTBEAT_REPLY, _) if body.len() >= 4 => {
+                let popularity = u32::from_be_bytes([body[0], body[1], body[2], body[3]]);
+                packets.push(packet("HeartbeatReply", &popularity.to_string(), room_id)?);
+            }
+            (OP_AUTH_REPLY, _) => {
+                debug!(
+                    "room {} auth reply: {}",
+                    room_id,
+                    String::from_utf8_lossy(body)
+                );
+            }
+            (operation, protover) => {
+                debug!(
+                    "room {} ignored packet op={} protover={}",
+                    room_id, operation, protover
+                );
+            }
+        }
+    }
+    Ok(packets)
+}
+
+/// 和录制文件里的格式一样，由 serde 构造 biliapi 的 Packet
+fn packet(operation: &str, body: &str, room_id: u64) -> Result<Packet> {
+    let packet = serde_json::json!({
+        "operation": operation,
+        "body": body,
+        "time": Local::now(),
+        "room_id": room_id,
+    });
+    Ok(serde_json::from_value(packet)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use biliapi::ws_protocol::{KnownOperation, Operation};
+
+    const ROOM_ID: u64 = 21452505;
+
+    fn cmd(packet: &Packet) -> String {
+        let body: serde_json::Value = serde_json::from_str(&packet.body).unwrap();
+        body["cmd"].as_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn heartbeat_is_encoded_like_the_web_client() {
+        let mut expected = vec![
+            0x00, 0x00, 0x00, 0x1f, 0x00, 0x10, 0x00, 0x01, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00,
+            0x00, 0x01,
+        ];
+        expected.extend_from_slice(b"[object Object]");
+        assert_eq!(encode(OP_HEARTBEAT, b"[object Object]"), expected);
+    }
+
+    #[test]
+    fn zlib_packet_is_expanded() {
+        let data = include_bytes!("../fixtures/frames/send_msg_reply_zlib.bin");
+        let packets = decode(data, ROOM_ID).unwrap();
+        assert_eq!(packets.len(), 2);
+        assert!(packets.iter().all(|p| p.room_id == ROOM_ID
+            && matches!(p.operation, Operation::Known(KnownOperation::SendMsgReply))));
+        assert_eq!(cmd(&packets[0]), "SUPER_CHAT_MESSAGE");
+        assert_eq!(cmd(&packets[1]), "GUARD_BUY");
+    }
+
+    #[test]
+    fn json_packet_is_decoded() {
+        let data = include_bytes!("../fixtures/frames/send_msg_reply_json.bin");
+        let packets = decode(data, ROOM_ID).unwrap();
+        assert_eq!(packets.len(), 1);
+        assert_eq!(cmd(&packets[0]), "GUARD_BUY");
+    }
+
+    #[test]
+    fn heartbeat_reply_is_popularity() {
+        // 认证回复不产生 packet
+        let data = include_bytes!("../fixtures/frames/auth_and_heartbeat_reply.bin");
+        let packets = decode(data, ROOM_ID).unwrap();
+        assert_eq!(packets.len(), 1);
+        assert!(matches!(
+            packets[0].operation,
+            Operation::Known(KnownOperation::HeartbeatReply)
+        ));
+        assert_eq!(packets[0].body, "6699");
+    }
+
+    #[test]
+    fn truncated_packet_is_rejected() {
+        let data = include_bytes!("../fixtures/frames/send_msg_reply_json.bin");
+        assert!(decode(&data[..data.len() - 1], ROOM_ID).is_err());
+    }
+
+    #[test]
+    fn zeroed_header_is_rejected() {
+        // 正常的帧后面跟着一个全 0 的头
+        let data = include_bytes!("../fixtures/frames/zeroed_header.bin");
+        assert!(decode(data, ROOM_ID).is_err());
+        assert!(decode(&data[data.len() - HEADER_LEN..], ROOM_ID).is_err());
+    }
+}
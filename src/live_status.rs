@@ -0,0 +1,261 @@
+//! 只在开播时连接
+//!
+//! 定期检查直播间是否开播，同时监听 `LIVE` / `PREPARING` 消息，
+//! 向 manager 发布正在直播（或下播后还在宽限期内）的直播间。
+//! 没有连接的直播间收不到 `LIVE`，检查到开播时另外通知提醒。
+
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use biliapi::ws_protocol::{KnownOperation, Operation, Packet};
+use tokio::{
+    sync::{
+        broadcast::{self, error::RecvError},
+        oneshot, watch,
+    },
+    time::sleep_until,
+};
+
+use crate::{
+    http::BiliHttp,
+    influx::messages::SendMsgReply,
+    requests::{RoomInit, StatusInfoByUids},
+    task_factory::TaskSet,
+};
+
+/// 解析短号之间的间隔
+const ROOM_INIT_INTERVAL: Duration = Duration::from_secs(1);
+/// 批量查询的大小
+const BATCH_SIZE: usize = 50;
+
+/// watch.toml 里的 `[live_only]`
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub struct LiveOnlyConfig {
+    /// 检查开播状态的间隔（秒）
+    #[serde(default = "default_poll_interval")]
+    pub poll_interval: u64,
+    /// 下播后继续监控多久（秒）
+    #[serde(default = "default_grace_period")]
+    pub grace_period: u64,
+}
+
+fn default_poll_interval() -> u64 {
+    60
+}
+fn default_grace_period() -> u64 {
+    10 * 60
+}
+
+#[derive(Debug)]
+struct RoomState {
+    long_id: u64,
+    uid: u64,
+    live: bool,
+    /// 最后一次看到开播的时间
+    last_live: Option<Instant>,
+}
+
+pub struct LiveStatusPoller {
+    config: watch::Receiver<Option<LiveOnlyConfig>>,
+    /// 需要检查的直播间
+    rooms: watch::Receiver<TaskSet>,
+    packets: broadcast::Receiver<Packet>,
+    /// None 时表示不过滤
+    publish: watch::Sender<Option<TaskSet>>,
+    /// 检查到开播的直播间（长号）
+    went_live: broadcast::Sender<u64>,
+    http: BiliHttp,
+    /// 按 watch.toml 里的房间号
+    states: HashMap<u64, RoomState>,
+    /// 上一次发布的直播间
+    last_published: Option<TaskSet>,
+}
+
+impl LiveStatusPoller {
+    pub fn new(
+        config: watch::Receiver<Option<LiveOnlyConfig>>,
+        rooms: watch::Receiver<TaskSet>,
+        packets: broadcast::Receiver<Packet>,
+        publish: watch::Sender<Option<TaskSet>>,
+        went_live: broadcast::Sender<u64>,
+        http: BiliHttp,
+    ) -> Self {
+        Self {
+            config,
+            rooms,
+            packets,
+            publish,
+            went_live,
+            http,
+            states: HashMap::new(),
+            last_published: None,
+        }
+    }
+
+    pub async fn start(self, stop: oneshot::Receiver<()>) -> Result<()> {
+        tokio::select! {
+            _ = stop => {
+                debug!("live status poller stopped");
+                Ok(())
+            },
+            ret = self.run() => {
+                error!("live status poller stopped unexpectedly: {:?}", ret);
+                ret
+            }
+        }
+    }
+
+    async fn run(mut self) -> Result<()> {
+        let mut next_poll = tokio::time::Instant::now();
+        loop {
+            let config = self.config.borrow().clone();
+            tokio::select! {
+                _ = sleep_until(next_poll) => {
+                    if let Some(config) = &config {
+                        self.poll().await;
+                        self.publish(config)?;
+                        next_poll = tokio::time::Instant::now()
+                            + Duration::from_secs(config.poll_interval);
+                    } else {
+                        next_poll = tokio::time::Instant::now() + Duration::from_secs(3600);
+                    }
+                }
+                ret = self.config.changed() => {
+                    ret?;
+                    if self.config.borrow().is_none() {
+                        info!("live only mode disabled.");
+                        self.last_published = None;
+                        self.publish.send(None)?;
+                    }
+                    next_poll = tokio::time::Instant::now();
+                }
+                ret = self.rooms.changed() => {
+                    ret?;
+                    next_poll = tokio::time::Instant::now();
+                }
+                recv = self.packets.recv() => match recv {
+                    Ok(packet) => {
+                        if let Some(config) = &config {
+                            if self.on_packet(packet) {
+                                self.publish(config)?;
+                            }
+                        }
+                    }
+                    Err(RecvError::Lagged(cnt)) => {
+                        warn!("live status poller lagged {} packets.", cnt);
+                    }
+                    Err(RecvError::Closed) => return Ok(()),
+                },
+            }
+        }
+    }
+
+    /// 检查所有直播间的开播状态
+    async fn poll(&mut self) {
+        let rooms = self.rooms.borrow().clone();
+        self.states.retain(|id, _| rooms.contains(id));
+
+        // 新的直播间先解析出长号和主播 uid
+        for &id in rooms.iter() {
+            if self.states.contains_key(&id) {
+                continue;
+            }
+            match RoomInit::request(&self.http, id).await {
+                Ok(init) => {
+                    let live = init.live_status == 1;
+                    self.states.insert(
+                        id,
+                        RoomState {
+                            long_id: init.room_id,
+                            uid: init.uid,
+                            live,
+                            last_live: if live { Some(Instant::now()) } else { None },
+                        },
+                    );
+                }
+                Err(e) => warn!("failed to get room init of {}: {:?}", id, e),
+            }
+            tokio::time::sleep(ROOM_INIT_INTERVAL).await;
+        }
+
+        let uids: Vec<u64> = self.states.values().map(|state| state.uid).collect();
+        for chunk in uids.chunks(BATCH_SIZE) {
+            let status = match StatusInfoByUids::request(&self.http, chunk).await {
+                Ok(status) => status,
+                Err(e) => {
+                    warn!("failed to get live status: {:?}", e);
+                    continue;
+                }
+            };
+            for info in status.0.values() {
+                for state in self.states.values_mut().filter(|s| s.uid == info.uid) {
+                    let live = info.live_status == 1;
+                    if live && !state.live {
+                        info!("room {} went live.", state.long_id);
+                        // 没有订阅者时发送失败，不影响轮询
+                        let _ = self.went_live.send(state.long_id);
+                    }
+                    state.live = live;
+                    if state.live {
+                        state.last_live = Some(Instant::now());
+                    }
+                }
+            }
+        }
+    }
+
+    /// 处理开播 / 下播消息，返回状态是否有变化
+    fn on_packet(&mut self, packet: Packet) -> bool {
+        match packet.operation {
+            Operation::Known(KnownOperation::SendMsgReply) => {}
+            _ => return false,
+        }
+        if !(packet.body.contains("\"cmd\":\"LIVE\"")
+            || packet.body.contains("\"cmd\":\"PREPARING\""))
+        {
+            return false;
+        }
+        let live = match serde_json::from_str::<SendMsgReply>(&packet.body) {
+            Ok(msg) if msg.cmd == "LIVE" => true,
+            Ok(msg) if msg.cmd == "PREPARING" => false,
+            _ => return false,
+        };
+        let mut changed = false;
+        for (id, state) in self.states.iter_mut() {
+            if state.long_id != packet.room_id || state.live == live {
+                continue;
+            }
+            info!("room {} is {}.", id, if live { "live" } else { "offline" });
+            state.live = live;
+            // 下播时从现在开始算宽限期
+            state.last_live = Some(Instant::now());
+            changed = true;
+        }
+        changed
+    }
+
+    fn publish(&mut self, config: &LiveOnlyConfig) -> Result<()> {
+        let grace = Duration::from_secs(config.grace_period);
+        let active: TaskSet = self
+            .states
+            .iter()
+            .filter(|(_, state)| {
+                state.live
+                    || state
+                        .last_live
+                        .map(|t| t.elapsed() < grace)
+                        .unwrap_or(false)
+            })
+            .map(|(&id, _)| id)
+            .collect();
+        if self.last_published.as_ref() != Some(&active) {
+            debug!("live rooms: {:?}", active);
+            self.last_published = Some(active.clone());
+            self.publish.send(Some(active))?;
+        }
+        Ok(())
+    }
+}
@@ -1,22 +1,16 @@
 #[macro_use]
-extern crate serde;
-#[macro_use]
 extern crate log;
 
 use anyhow::Result;
 use clap::Clap;
-use influxdb_client::{Client as InfluxClient, Precision};
-use std::path::PathBuf;
-
-mod file_appender;
-mod influx;
-mod manager;
-mod monitor;
-mod replayer;
-mod spider;
-mod task_factory;
+use std::{net::SocketAddr, path::PathBuf, time::Duration};
 
-use manager::Manager;
+use ddpanel::{
+    http::HttpConfig,
+    influx::{self, config::InfluxConfig, sender_schema::SenderSchema},
+    manager::Manager,
+    revenue,
+};
 
 #[derive(Debug, clap::Clap)]
 struct Opts {
@@ -39,6 +33,89 @@ struct Opts {
     #[clap(long = "no-influx", about = "Do not write to influxdb.")]
     no_influx: bool,
 
+    #[clap(
+        long = "influx-config",
+        default_value = "influx.toml",
+        about = "Influxdb url, org, buckets, measurement prefix and global tags. Env vars override it"
+    )]
+    influx_config: PathBuf,
+
+    #[clap(
+        long = "influx-file",
+        about = "Write gzip line protocol to this file instead of posting to influxdb, e.g. bundle.lp.gz"
+    )]
+    influx_file: Option<PathBuf>,
+
+    #[clap(
+        long = "merge-combo",
+        about = "Write each gift combo as one point instead of one point per SEND_GIFT"
+    )]
+    merge_combo: bool,
+
+    #[clap(
+        long = "prometheus",
+        about = "Expose live room metrics for prometheus on this address, e.g. 0.0.0.0:9100"
+    )]
+    prometheus: Option<SocketAddr>,
+
+    #[clap(
+        long = "admin",
+        about = "Serve the admin api on this address, e.g. 127.0.0.1:8000"
+    )]
+    admin: Option<SocketAddr>,
+
+    #[clap(
+        long = "authed-danmu",
+        about = "Connect danmu servers with the spider's login so sender names and uids are complete"
+    )]
+    authed_danmu: bool,
+
+    #[clap(
+        long = "http-rate",
+        default_value = "5",
+        about = "Max bilibili api requests per second"
+    )]
+    http_rate: f64,
+
+    #[clap(
+        long = "http-burst",
+        default_value = "10",
+        about = "Max burst of bilibili api requests"
+    )]
+    http_burst: u32,
+
+    #[clap(
+        long = "proxy",
+        about = "Rotate bilibili api requests through this proxy, can be repeated"
+    )]
+    proxies: Vec<String>,
+
+    #[clap(
+        long = "ban-cooldown",
+        default_value = "300",
+        about = "Seconds to pause all bilibili api requests after a 412 response"
+    )]
+    ban_cooldown: u64,
+
+    #[clap(
+        long = "http-base-url",
+        about = "Send bilibili api requests to this base url instead, e.g. a mirror or a local mock"
+    )]
+    http_base_url: Option<String>,
+
+    #[clap(
+        long = "streamer-share",
+        about = "Percent of revenue the streamer receives in batteries, written as the share field"
+    )]
+    streamer_share: Option<u32>,
+
+    #[clap(
+        long = "sender-schema",
+        default_value = "tag",
+        about = "How gift senders are written: tag, field, hash, bucket[:N] or drop"
+    )]
+    sender_schema: SenderSchema,
+
     #[clap(long = "replay", short = 'r', about = "Replay the file")]
     replay: Option<String>,
 
@@ -67,22 +144,6 @@ struct Opts {
     cookie_path: PathBuf,
 }
 
-impl Opts {
-    pub fn influx_client(&self) -> Result<InfluxClient> {
-        const DEFAULT_ADDR: &str = "127.0.0.1:8086";
-        let domain = std::env::var("INFLUX_ADDR").unwrap_or_else(|_e| {
-            info!("using default address {}", DEFAULT_ADDR);
-            DEFAULT_ADDR.to_string()
-        });
-        let host = format!("http://{}", domain);
-        let token = std::env::var("INFLUX_TOKEN")?;
-        Ok(InfluxClient::new(host, token)?
-            .with_org("ddpanel")
-            .with_bucket("ddpanel")
-            .with_precision(Precision::MS))
-    }
-}
-
 #[tokio::main]
 async fn main() -> Result<()> {
     if dotenv::dotenv().is_err() {
@@ -92,8 +153,18 @@ async fn main() -> Result<()> {
     debug!("log4rs initialized.");
 
     let mut opts = Opts::parse();
+    influx::messages::set_sender_schema(opts.sender_schema);
+    if let Some(percent) = opts.streamer_share {
+        revenue::set_streamer_share(percent);
+    }
 
-    let mut manager = Manager::new();
+    let mut manager = Manager::new().http_config(HttpConfig {
+        rate: opts.http_rate,
+        burst: opts.http_burst,
+        proxies: opts.proxies.clone(),
+        ban_cooldown: Duration::from_secs(opts.ban_cooldown),
+        base_url: opts.http_base_url.clone(),
+    });
 
     if opts.replay.is_some() {
         opts.no_file = true;
@@ -101,18 +172,38 @@ async fn main() -> Result<()> {
 
     if !opts.no_influx {
         let buffer_size = if opts.replay.is_some() { 128 } else { 0 };
-        manager = manager.influx_appender(opts.influx_client()?, buffer_size);
+        let mut influx_config = InfluxConfig::load(&opts.influx_config)?;
+        if let Some(file) = opts.influx_file.clone() {
+            influx_config.file = Some(file);
+        }
+        influx_config.set_point_template();
+        manager = manager.influx_appender(
+            influx_config.client()?,
+            influx_config.spider_client()?,
+            buffer_size,
+            opts.merge_combo,
+        );
     }
     if !opts.no_file {
         manager = manager
             .file_appender(opts.record_file, opts.bili_file)
             .await?;
     }
-    if opts.no_file && opts.no_influx {
+    if let Some(addr) = opts.prometheus {
+        manager = manager.prometheus_exporter(addr);
+    }
+    if opts.no_file && opts.no_influx && opts.prometheus.is_none() {
         // 至少要一个 appender 才可以
         manager = manager.no_appender();
     }
 
+    if let Some(addr) = opts.admin {
+        manager = manager.admin_api(addr);
+    }
+    if opts.authed_danmu {
+        manager = manager.authed_danmu();
+    }
+
     if let Some(replay) = opts.replay {
         // always disable file output
         manager.replay(replay, opts.replay_delay_ms).await?;
@@ -1,32 +1,71 @@
 //! 总经理
 
-use std::{collections::HashMap, path::PathBuf};
+use std::{collections::HashMap, net::SocketAddr, path::PathBuf, sync::Arc};
 
 use anyhow::Result;
 use biliapi::ws_protocol::Packet;
-use influxdb_client::Client as InfluxClient;
 use parking_lot::RwLock;
-use tokio::sync::{broadcast, oneshot, watch};
+use reqwest_cookie_store::CookieStoreMutex;
+use tokio::sync::{broadcast, mpsc, oneshot, watch, Notify};
 
 use crate::{
+    admin::{AdminCommand, AdminServer, RoomStatus, Status},
+    alert::{AlertRule, Alerter},
+    discovery::{Discovery, DiscoveryConfig},
     file_appender::FileAppender,
-    influx::InfluxAppender,
-    monitor::Monitor,
+    http::{BiliHttp, HttpConfig},
+    influx::{InfluxAppender, InfluxSink, RoomInfo},
+    keyword::KeywordRule,
+    live_status::{LiveOnlyConfig, LiveStatusPoller},
+    monitor::{Monitor, MonitorStatus},
+    prometheus::PrometheusExporter,
     replayer::FileReplayer,
-    spider::SpiderInfo,
-    task_factory::{TaskFactory, TaskSet},
+    spider::{SpiderInfo, SpiderSchedules},
+    task_factory::{Config, RoomOptions, TaskFactory, TaskSet},
 };
 
 // FIXME: 这是一个丑陋的打洞实现，需要修改为更好的实现
 lazy_static::lazy_static! {
-    pub static ref ROOM_ID_TO_STREAMER: RwLock<HashMap<u64, String>> = Default::default();
+    pub static ref ROOM_INFOS: RwLock<HashMap<u64, RoomInfo>> = Default::default();
+    /// watch.toml 里的房间号 => 长号，短号对应的长号不会变，停止监控后也保留
+    pub static ref LONG_ROOM_IDS: RwLock<HashMap<u64, u64>> = Default::default();
+}
+
+/// 合并 watch.toml 里的直播间和自动发现的直播间
+///
+/// 自动发现的是长号，可能已经以短号写在 watch.toml 里了。long_room_id 为已知的短号对应的长号，
+/// 不依赖 monitor 是否在运行，自动发现的直播间掉出榜单后再回来也会重新加入。
+fn merge_discovered(
+    configured: &TaskSet,
+    discovered: &TaskSet,
+    long_room_id: impl Fn(u64) -> Option<u64>,
+) -> TaskSet {
+    let configured_long: TaskSet = configured
+        .iter()
+        .filter_map(|&id| long_room_id(id).filter(|&long_id| long_id != id))
+        .collect();
+    let mut live_rooms = configured.clone();
+    live_rooms.extend(
+        discovered
+            .iter()
+            .filter(|room_id| !configured_long.contains(room_id)),
+    );
+    live_rooms
+}
+
+/// 一个运行中的 monitor
+struct MonitorHandle {
+    /// 接收到结束信号的时候，会向 monitor 发送结束信号
+    terminate: oneshot::Sender<()>,
+    reconnect: Arc<Notify>,
+    status: watch::Receiver<MonitorStatus>,
+    options: watch::Sender<RoomOptions>,
 }
 
 pub struct Manager {
     /// 主通信渠道
     packet_channel: broadcast::Sender<Packet>,
-    /// 接收到结束信号的时候，会向各个 monitor 发送结束信号
-    monitor_terminate_senders: HashMap<u64, oneshot::Sender<()>>,
+    monitors: HashMap<u64, MonitorHandle>,
 
     /// 等待各个 subscriber 结束的 handler
     subscriber_handlers: Vec<tokio::task::JoinHandle<Result<()>>>,
@@ -37,6 +76,65 @@ pub struct Manager {
     spider_tasks_channel: (watch::Sender<TaskSet>, watch::Receiver<TaskSet>),
     /// 爬虫信息通道
     spider_channel: broadcast::Sender<SpiderInfo>,
+    /// 手动触发爬虫，每触发一次加一
+    spider_trigger: (watch::Sender<u64>, watch::Receiver<u64>),
+    /// 爬虫任务的调度
+    spider_schedules: (
+        watch::Sender<SpiderSchedules>,
+        watch::Receiver<SpiderSchedules>,
+    ),
+
+    /// 当前的任务
+    tasks: Option<Config>,
+    /// 自动发现停止
+    discovery_stop: Option<oneshot::Sender<()>>,
+    /// 自动发现的配置
+    discovery_config: (
+        watch::Sender<Option<DiscoveryConfig>>,
+        watch::Receiver<Option<DiscoveryConfig>>,
+    ),
+    /// 自动发现的直播间
+    discovered: watch::Receiver<TaskSet>,
+
+    /// 提醒停止
+    alert_stop: Option<oneshot::Sender<()>>,
+    /// 提醒规则
+    alert_rules: (
+        watch::Sender<Vec<AlertRule>>,
+        watch::Receiver<Vec<AlertRule>>,
+    ),
+    /// 弹幕关键词
+    keyword_rules: (
+        watch::Sender<Vec<KeywordRule>>,
+        watch::Receiver<Vec<KeywordRule>>,
+    ),
+
+    /// 开播检查停止
+    live_status_stop: Option<oneshot::Sender<()>>,
+    /// 只在开播时连接的配置
+    live_only_config: (
+        watch::Sender<Option<LiveOnlyConfig>>,
+        watch::Receiver<Option<LiveOnlyConfig>>,
+    ),
+    /// 需要检查开播状态的直播间
+    live_candidates: (watch::Sender<TaskSet>, watch::Receiver<TaskSet>),
+    /// 正在直播（或下播不久）的直播间，None 时不过滤
+    live_filter: watch::Receiver<Option<TaskSet>>,
+
+    /// 管理接口的地址
+    admin_addr: Option<SocketAddr>,
+    /// b 站请求的限速和代理
+    http_config: HttpConfig,
+    /// 用爬虫的登录信息连接弹幕服务器
+    authed_danmu: bool,
+    /// authed_danmu 时为爬虫的 cookie
+    danmu_cookies: Option<Arc<CookieStoreMutex>>,
+}
+
+impl Default for Manager {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Manager {
@@ -44,21 +142,42 @@ impl Manager {
         let (packet_sender, _) = broadcast::channel::<Packet>(10_000);
         let (spider_channel, _) = broadcast::channel::<SpiderInfo>(1_000);
         let spider_tasks_channel = watch::channel(Default::default());
+        let (_, discovered) = watch::channel(Default::default());
+        let (_, live_filter) = watch::channel(None);
 
         Self {
             packet_channel: packet_sender,
-            monitor_terminate_senders: HashMap::new(),
+            monitors: HashMap::new(),
             subscriber_handlers: vec![],
             spider_stop: None,
             spider_tasks_channel,
             spider_channel,
+            spider_trigger: watch::channel(0),
+            spider_schedules: watch::channel(Default::default()),
+            tasks: None,
+            discovery_stop: None,
+            discovery_config: watch::channel(None),
+            discovered,
+            alert_stop: None,
+            alert_rules: watch::channel(vec![]),
+            keyword_rules: watch::channel(vec![]),
+            live_status_stop: None,
+            live_only_config: watch::channel(None),
+            live_candidates: watch::channel(Default::default()),
+            live_filter,
+            admin_addr: None,
+            http_config: HttpConfig::default(),
+            authed_danmu: false,
+            danmu_cookies: None,
         }
     }
 
     /// add file appender (consumer)
     pub async fn file_appender(mut self, live_path: String, bili_path: String) -> Result<Self> {
         let receiver = self.packet_channel.subscribe();
-        let appender = FileAppender::new(live_path, receiver).await?;
+        let appender = FileAppender::new(live_path, receiver)
+            .await?
+            .filter(Self::should_record);
         let handler = tokio::spawn(appender.start());
         self.subscriber_handlers.push(handler);
 
@@ -70,14 +189,40 @@ impl Manager {
         Ok(self)
     }
 
-    /// 增加一个 influx 插入，buffer size >0 才有效
-    pub fn influx_appender(mut self, influx_client: InfluxClient, buffer_size: usize) -> Self {
+    /// 按房间设置过滤写入原始文件的 packet
+    fn should_record(packet: &Packet) -> bool {
+        RoomInfo::options_of(packet.room_id, |options| {
+            if !options.record_raw {
+                return false;
+            }
+            options.store_danmu || !packet.body.contains("\"cmd\":\"DANMU_MSG")
+        })
+    }
+
+    /// 增加一个 influx 插入，buffer size >0 才有效，merge_combo 时连击的礼物合并为一个点。
+    /// 有 spider_client 时爬虫数据写入其中
+    pub fn influx_appender(
+        mut self,
+        influx_client: InfluxSink,
+        spider_client: Option<InfluxSink>,
+        buffer_size: usize,
+        merge_combo: bool,
+    ) -> Self {
         let packets_receiver = self.packet_channel.subscribe();
         let spider_receiver = self.spider_channel.subscribe();
-        let mut appender = InfluxAppender::new(influx_client, packets_receiver, spider_receiver);
+        let mut appender = InfluxAppender::new(
+            influx_client,
+            packets_receiver,
+            spider_receiver,
+            self.keyword_rules.1.clone(),
+        )
+        .merge_combo(merge_combo);
         if buffer_size > 0 {
             appender = appender.buffer_size(buffer_size);
         }
+        if let Some(spider_client) = spider_client {
+            appender = appender.spider_client(spider_client);
+        }
 
         let handler = tokio::spawn(appender.start());
         self.subscriber_handlers.push(handler);
@@ -85,6 +230,33 @@ impl Manager {
         self
     }
 
+    /// 增加一个 prometheus exporter，在 addr 上暴露 /metrics
+    pub fn prometheus_exporter(mut self, addr: SocketAddr) -> Self {
+        let receiver = self.packet_channel.subscribe();
+        let exporter = PrometheusExporter::new(addr, receiver);
+        let handler = tokio::spawn(exporter.start());
+        self.subscriber_handlers.push(handler);
+        self
+    }
+
+    /// 在 addr 上开启管理接口，只在 start 模式下有效
+    pub fn admin_api(mut self, addr: SocketAddr) -> Self {
+        self.admin_addr = Some(addr);
+        self
+    }
+
+    /// 设置 b 站请求的限速和代理
+    pub fn http_config(mut self, config: HttpConfig) -> Self {
+        self.http_config = config;
+        self
+    }
+
+    /// 用爬虫登录的账号连接弹幕，弹幕里才有完整的用户名和 uid
+    pub fn authed_danmu(mut self) -> Self {
+        self.authed_danmu = true;
+        self
+    }
+
     pub fn no_appender(mut self) -> Self {
         let receiver = self.packet_channel.subscribe();
         let handler = tokio::spawn(async move {
@@ -108,66 +280,91 @@ impl Manager {
 
     /// run with task factory, never end
     pub async fn start(mut self, task_file: PathBuf, cookie_path: PathBuf) -> Result<()> {
-        let http_client = biliapi::connection::new_client()?;
+        let http_client = BiliHttp::new(self.http_config.clone())?;
 
-        let mut task_receiver = TaskFactory::start(task_file);
+        let (mut task_receiver, task_commands) = TaskFactory::start(task_file);
 
         let spider = crate::spider::Spider::new(
             self.spider_tasks_channel.1.clone(),
+            self.spider_schedules.1.clone(),
             self.spider_channel.clone(),
+            &http_client,
             cookie_path,
+            self.spider_trigger.1.clone(),
         );
+        // 共用爬虫带 cookie 的客户端，拿到的 token 才对应登录的账号
+        let monitor_client = if self.authed_danmu {
+            self.danmu_cookies = Some(spider.cookies());
+            spider.http()
+        } else {
+            http_client.clone()
+        };
         let (tx, rx) = oneshot::channel();
         tokio::spawn(spider.start(rx));
         self.spider_stop = Some(tx);
 
+        let (discovered_sender, discovered) = watch::channel(Default::default());
+        let discovery = Discovery::new(
+            self.discovery_config.1.clone(),
+            discovered_sender,
+            http_client.clone(),
+        );
+        let (tx, rx) = oneshot::channel();
+        tokio::spawn(discovery.start(rx));
+        self.discovery_stop = Some(tx);
+        self.discovered = discovered;
+
+        // 第一次检查开播状态之前不连接，不是 live-only 模式时 poller 收到配置后会发 None
+        let (live_filter_sender, live_filter) = watch::channel(Some(TaskSet::new()));
+        let (went_live_sender, went_live) = broadcast::channel(16);
+        let poller = LiveStatusPoller::new(
+            self.live_only_config.1.clone(),
+            self.live_candidates.1.clone(),
+            self.packet_channel.subscribe(),
+            live_filter_sender,
+            went_live_sender,
+            http_client.clone(),
+        );
+        let (tx, rx) = oneshot::channel();
+        tokio::spawn(poller.start(rx));
+        self.live_status_stop = Some(tx);
+        self.live_filter = live_filter;
+
+        let alerter = Alerter::new(
+            self.alert_rules.1.clone(),
+            self.keyword_rules.1.clone(),
+            self.packet_channel.subscribe(),
+            went_live,
+        )?;
+        let (tx, rx) = oneshot::channel();
+        tokio::spawn(alerter.start(rx));
+        self.alert_stop = Some(tx);
+
+        let (admin_sender, mut admin_receiver) = mpsc::channel(5);
+        if let Some(addr) = self.admin_addr {
+            let admin = AdminServer::new(admin_sender, task_commands);
+            tokio::spawn(async move {
+                if let Err(e) = admin.serve(addr).await {
+                    error!("admin api stopped: {:?}", e);
+                }
+            });
+        } else {
+            std::mem::drop((admin_sender, task_commands));
+        }
+
         // 一直等到 task_receiver 结束
         loop {
-            let recv = task_receiver.recv().await;
-            match recv {
-                Some(tasks) => {
-                    let live_rooms = tasks.live_rooms;
-
-                    // check for tasks
-                    let cur_rooms: TaskSet =
-                        self.monitor_terminate_senders.keys().cloned().collect();
-                    // terminate old tasks
-                    let stop_rooms = cur_rooms.difference(&live_rooms);
-                    for stop_id in stop_rooms {
-                        info!("Stopping monitor room {}", stop_id);
-                        // safety: stop_id 一定在 cur_tasks 中
-                        let sender = self.monitor_terminate_senders.remove(stop_id).unwrap();
-                        if sender.send(()).is_err() {
-                            error!(
-                                "Send terminate to id {} but the monitor is already dead.",
-                                stop_id
-                            );
-                        };
+            tokio::select! {
+                recv = task_receiver.recv() => match recv {
+                    Some(tasks) => self.on_tasks(tasks, &monitor_client),
+                    None => {
+                        debug!("manger noticed that task factory has stopped.");
+                        break;
                     }
-                    // start new tasks
-                    let new_rooms = live_rooms.difference(&cur_rooms);
-                    for &new_id in new_rooms {
-                        info!("start new monitor room: {}", new_id);
-                        let (terminate_sender, terminate_receiver) = oneshot::channel();
-                        let monitor =
-                            Monitor::new(new_id, self.packet_channel.clone(), http_client.clone());
-                        tokio::spawn(monitor.start(terminate_receiver));
-                        self.monitor_terminate_senders
-                            .insert(new_id, terminate_sender);
-                    }
-
-                    // send to spider
-                    match self.spider_tasks_channel.0.send(tasks.users) {
-                        Ok(_) => {}
-                        Err(e) => {
-                            error!("failed to send tasks on spider tasks chanel: {:?}", e);
-                        }
-                    }
-                }
-                None => {
-                    debug!("manger noticed that task factory has stopped.");
-                    break;
-                }
+                },
+                Ok(()) = self.discovered.changed() => self.sync_monitors(&monitor_client),
+                Ok(()) = self.live_filter.changed() => self.sync_monitors(&monitor_client),
+                Some(command) = admin_receiver.recv() => self.on_admin_command(command),
             }
         }
 
@@ -176,8 +373,204 @@ impl Manager {
         Ok(())
     }
 
+    fn on_tasks(&mut self, tasks: Config, http_client: &BiliHttp) {
+        // send to discovery
+        if self
+            .discovery_config
+            .0
+            .send(tasks.discovery.clone())
+            .is_err()
+        {
+            error!("failed to send config to discovery");
+        }
+        // send to alerter
+        let alerts_changed = self
+            .tasks
+            .as_ref()
+            .map(|old| old.alerts != tasks.alerts)
+            .unwrap_or(true);
+        if alerts_changed && self.alert_rules.0.send(tasks.alerts.clone()).is_err() {
+            error!("failed to send alert rules");
+        }
+        // send to keyword matchers
+        let keywords_changed = self
+            .tasks
+            .as_ref()
+            .map(|old| old.keywords != tasks.keywords)
+            .unwrap_or(true);
+        if keywords_changed && self.keyword_rules.0.send(tasks.keywords.clone()).is_err() {
+            error!("failed to send keyword rules");
+        }
+        // send to live status poller，第一次总是发送，poller 收到 None 后取消过滤
+        let live_only_changed = self
+            .tasks
+            .as_ref()
+            .map(|old| old.live_only != tasks.live_only)
+            .unwrap_or(true);
+        if live_only_changed
+            && self
+                .live_only_config
+                .0
+                .send(tasks.live_only.clone())
+                .is_err()
+        {
+            error!("failed to send live only config");
+        }
+        // send to spider
+        match self.spider_tasks_channel.0.send(tasks.users.clone()) {
+            Ok(_) => {}
+            Err(e) => {
+                error!("failed to send tasks on spider tasks chanel: {:?}", e);
+            }
+        }
+        // 调度没变时不通知，避免每次重新加载都立刻跑一轮
+        let schedules_changed = self
+            .tasks
+            .as_ref()
+            .map(|old| old.spider != tasks.spider)
+            .unwrap_or(true);
+        if schedules_changed && self.spider_schedules.0.send(tasks.spider.clone()).is_err() {
+            error!("failed to send spider schedules");
+        }
+
+        self.tasks = Some(tasks);
+        self.sync_monitors(http_client);
+    }
+
+    /// watch.toml 里的直播间和自动发现的直播间
+    fn live_rooms(&self) -> TaskSet {
+        let configured = match &self.tasks {
+            Some(tasks) => tasks.all_rooms(),
+            None => TaskSet::new(),
+        };
+        let long_room_ids = LONG_ROOM_IDS.read();
+        merge_discovered(&configured, &self.discovered.borrow(), |id| {
+            long_room_ids.get(&id).copied()
+        })
+    }
+
+    fn room_options(&self, room_id: u64) -> RoomOptions {
+        match &self.tasks {
+            Some(tasks) if tasks.contains_room(room_id) => tasks.room_options(room_id),
+            // 自动发现的直播间不爬 up 主
+            _ => RoomOptions {
+                spider_uploader: false,
+                ..Default::default()
+            },
+        }
+    }
+
+    /// 按当前的直播间启停 monitor
+    fn sync_monitors(&mut self, http_client: &BiliHttp) {
+        let mut live_rooms = self.live_rooms();
+        if *self.live_candidates.1.borrow() != live_rooms
+            && self.live_candidates.0.send(live_rooms.clone()).is_err()
+        {
+            error!("failed to send rooms to live status poller");
+        }
+        let watched_rooms = live_rooms.clone();
+        // 只在开播时连接
+        if let Some(active) = &*self.live_filter.borrow() {
+            live_rooms.retain(|room_id| active.contains(room_id));
+        }
+
+        // check for tasks
+        let cur_rooms: TaskSet = self.monitors.keys().cloned().collect();
+        // terminate old tasks
+        let stop_rooms = cur_rooms.difference(&live_rooms);
+        for stop_id in stop_rooms {
+            info!("Stopping monitor room {}", stop_id);
+            // safety: stop_id 一定在 cur_tasks 中
+            let handle = self.monitors.remove(stop_id).unwrap();
+            // 下播只是断开连接，房间被移出监控列表才不再爬这个主播
+            if !watched_rooms.contains(stop_id) {
+                RoomInfo::unwatch(*stop_id);
+            }
+            if handle.terminate.send(()).is_err() {
+                error!(
+                    "Send terminate to id {} but the monitor is already dead.",
+                    stop_id
+                );
+            };
+        }
+        // update options of running tasks
+        for (id, handle) in self.monitors.iter() {
+            let _ = handle.options.send(self.room_options(*id));
+        }
+        // start new tasks
+        let new_rooms = live_rooms.difference(&cur_rooms);
+        for &new_id in new_rooms {
+            info!("start new monitor room: {}", new_id);
+            let (terminate_sender, terminate_receiver) = oneshot::channel();
+            let (status_sender, status_receiver) = watch::channel(MonitorStatus::Starting);
+            let reconnect = Arc::new(Notify::new());
+            let (options_sender, options_receiver) = watch::channel(self.room_options(new_id));
+            let monitor = Monitor::new(
+                new_id,
+                self.packet_channel.clone(),
+                http_client.clone(),
+                status_sender,
+                reconnect.clone(),
+                options_receiver,
+                self.danmu_cookies.clone(),
+            );
+            tokio::spawn(monitor.start(terminate_receiver));
+            self.monitors.insert(
+                new_id,
+                MonitorHandle {
+                    terminate: terminate_sender,
+                    reconnect,
+                    status: status_receiver,
+                    options: options_sender,
+                },
+            );
+        }
+    }
+
+    fn on_admin_command(&mut self, command: AdminCommand) {
+        match command {
+            AdminCommand::Status(reply) => {
+                let mut rooms: Vec<_> = self
+                    .monitors
+                    .iter()
+                    .map(|(&room_id, handle)| RoomStatus {
+                        room_id,
+                        status: handle.status.borrow().clone(),
+                    })
+                    .collect();
+                rooms.sort_by_key(|r| r.room_id);
+                let mut users: Vec<_> = self
+                    .spider_tasks_channel
+                    .1
+                    .borrow()
+                    .iter()
+                    .cloned()
+                    .collect();
+                users.sort_unstable();
+                let _ = reply.send(Status { rooms, users });
+            }
+            AdminCommand::Reconnect(room_id, reply) => {
+                let found = match self.monitors.get(&room_id) {
+                    Some(handle) => {
+                        info!("reconnect room {} on request.", room_id);
+                        handle.reconnect.notify_one();
+                        true
+                    }
+                    None => false,
+                };
+                let _ = reply.send(found);
+            }
+            AdminCommand::RunSpider => {
+                let generation = *self.spider_trigger.1.borrow() + 1;
+                if self.spider_trigger.0.send(generation).is_err() {
+                    error!("failed to trigger spider");
+                }
+            }
+        }
+    }
+
     pub async fn replay(self, replay_file: String, replay_delay_ms: u32) -> Result<()> {
-        let http_client = biliapi::connection::new_client()?;
+        let http_client = BiliHttp::new(self.http_config.clone())?;
 
         let mut replayer =
             FileReplayer::new(self.packet_channel.clone(), http_client, replay_delay_ms).await?;
@@ -201,9 +594,24 @@ impl Manager {
                 warn!("failed to stop spider: {:?}", e);
             }
         }
+        if let Some(t) = self.discovery_stop {
+            if t.send(()).is_err() {
+                warn!("failed to stop discovery");
+            }
+        }
+        if let Some(t) = self.live_status_stop {
+            if t.send(()).is_err() {
+                warn!("failed to stop live status poller");
+            }
+        }
+        if let Some(t) = self.alert_stop {
+            if t.send(()).is_err() {
+                warn!("failed to stop alerter");
+            }
+        }
 
-        for (_id, terminator) in self.monitor_terminate_senders.into_iter() {
-            if terminator.send(()).is_err() {
+        for (_id, handle) in self.monitors.into_iter() {
+            if handle.terminate.send(()).is_err() {
                 warn!("A monitor has already died.");
             };
         }
@@ -233,3 +641,37 @@ impl Manager {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(ids: &[u64]) -> TaskSet {
+        ids.iter().copied().collect()
+    }
+
+    #[test]
+    fn discovered_rooms_skip_configured_short_ids() {
+        // 92613 是 21452505 的短号
+        let long_room_id = |id| if id == 92613 { Some(21452505) } else { None };
+        let configured = set(&[92613, 22637261]);
+
+        let rooms = merge_discovered(&configured, &set(&[21452505, 7777]), long_room_id);
+        assert_eq!(rooms, set(&[92613, 22637261, 7777]));
+
+        // 掉出榜单
+        let rooms = merge_discovered(&configured, &set(&[21452505]), long_room_id);
+        assert_eq!(rooms, set(&[92613, 22637261]));
+
+        // 回到榜单后重新加入
+        let rooms = merge_discovered(&configured, &set(&[21452505, 7777]), long_room_id);
+        assert_eq!(rooms, set(&[92613, 22637261, 7777]));
+    }
+
+    #[test]
+    fn discovered_rooms_without_known_short_id() {
+        // 短号还没解析出长号时先按不同的房间处理
+        let rooms = merge_discovered(&set(&[92613]), &set(&[21452505]), |_| None);
+        assert_eq!(rooms, set(&[92613, 21452505]));
+    }
+}
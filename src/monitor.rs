@@ -1,30 +1,69 @@
 use anyhow::{anyhow, bail, Result};
 use biliapi::{ws_protocol::Packet, Request};
+use chrono::{DateTime, Local};
 use futures::StreamExt;
-use reqwest::Client as HttpClient;
-use std::time::{Duration, Instant};
-use tokio::sync::{broadcast, oneshot};
+use reqwest_cookie_store::CookieStoreMutex;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::sync::{broadcast, oneshot, watch, Notify};
 
-use crate::influx::RoomInfo;
+use crate::{
+    http::BiliHttp,
+    influx::RoomInfo,
+    live_connection::{self, LiveAuth},
+    task_factory::RoomOptions,
+};
 
 static ALLOW_FAIL_DURATION: Duration = Duration::from_secs(5 * 60);
 
+/// monitor 的连接状态
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum MonitorStatus {
+    Starting,
+    Connected {
+        streamer: String,
+        since: DateTime<Local>,
+    },
+    Reconnecting {
+        error: String,
+    },
+}
+
 pub struct Monitor {
     room_id: u64,
     broadcaster: broadcast::Sender<Packet>,
-    http_client: HttpClient,
+    http: BiliHttp,
+    /// 对外汇报连接状态
+    status: watch::Sender<MonitorStatus>,
+    /// 收到通知时断开并重连
+    reconnect: Arc<Notify>,
+    /// watch.toml 里的房间设置
+    options: watch::Receiver<RoomOptions>,
+    /// 有时用爬虫的登录信息连接弹幕服务器
+    auth: Option<Arc<CookieStoreMutex>>,
 }
 
 impl Monitor {
     pub fn new(
         room_id: u64,
         broadcaster: broadcast::Sender<Packet>,
-        http_client: HttpClient,
+        http: BiliHttp,
+        status: watch::Sender<MonitorStatus>,
+        reconnect: Arc<Notify>,
+        options: watch::Receiver<RoomOptions>,
+        auth: Option<Arc<CookieStoreMutex>>,
     ) -> Self {
         Self {
             room_id,
             broadcaster,
-            http_client,
+            http,
+            status,
+            reconnect,
+            options,
+            auth,
         }
     }
     pub async fn start(mut self, terminate_receiver: oneshot::Receiver<()>) -> Result<()> {
@@ -42,20 +81,41 @@ impl Monitor {
 
     async fn start_live_monitor_with_retry(&mut self) -> Result<()> {
         debug!("run_with_retry: room_id = {}", self.room_id);
-        let room_info =
-            biliapi::requests::InfoByRoom::request(&self.http_client, self.room_id).await?;
+        let room_id = self.room_id;
+        let room_info = self
+            .http
+            .request(|client| async move {
+                biliapi::requests::InfoByRoom::request(&client, room_id).await
+            })
+            .await?;
         let long_room_id = room_info.room_info.room_id;
         let streamer = room_info.anchor_info.base.uname;
 
         let mut last_time = Instant::now();
         let mut err_counter = 0;
-        RoomInfo::write_cache(long_room_id, streamer.clone());
+        crate::manager::LONG_ROOM_IDS
+            .write()
+            .insert(self.room_id, long_room_id);
+        RoomInfo::write_cache(RoomInfo {
+            watch_id: self.room_id,
+            anchor_uid: Some(room_info.room_info.uid),
+            options: self.options.borrow().clone(),
+            ..RoomInfo::new(long_room_id, streamer.clone())
+        });
 
         loop {
             match self.live_monitor(long_room_id, &streamer).await {
-                Ok(_) => unreachable!(),
+                Ok(_) => {
+                    info!(
+                        "room {} ({}) reconnecting on request.",
+                        long_room_id, streamer
+                    );
+                }
                 Err(e) => {
                     warn!("发生错误：{:?}", e);
+                    let _ = self.status.send(MonitorStatus::Reconnecting {
+                        error: e.to_string(),
+                    });
                     if Instant::now().duration_since(last_time) < ALLOW_FAIL_DURATION {
                         warn!("错误发生过于频繁！ err_counter = {}", err_counter);
                         err_counter += 1;
@@ -76,17 +136,53 @@ impl Monitor {
         }
     }
 
+    /// 一直接收直到出错；收到重连通知时返回 Ok
     async fn live_monitor(&mut self, long_room_id: u64, streamer: &str) -> Result<()> {
         // 拿到弹幕数据
-        let danmu_info =
-            biliapi::requests::DanmuInfo::request(&self.http_client, long_room_id).await?;
+        let danmu_info = self
+            .http
+            .request(|client| async move {
+                biliapi::requests::DanmuInfo::request(&client, long_room_id).await
+            })
+            .await?;
         let server = &danmu_info.servers[0];
         let url = server.url();
 
-        let mut connection =
-            biliapi::connection::LiveConnection::new(&url, long_room_id, danmu_info.token).await?;
+        let auth = self.auth.as_deref().and_then(LiveAuth::from_cookies);
+        let mut connection = match &auth {
+            Some(auth) => {
+                live_connection::connect(&url, long_room_id, danmu_info.token, auth).await?
+            }
+            None => {
+                if self.auth.is_some() {
+                    warn!(
+                        "spider is not logged in, room {} connecting anonymously.",
+                        long_room_id
+                    );
+                }
+                biliapi::connection::LiveConnection::new(&url, long_room_id, danmu_info.token)
+                    .await?
+                    .map(|packet| packet.map_err(anyhow::Error::from))
+                    .boxed()
+            }
+        };
         info!("room {} ({}) connected.", long_room_id, streamer);
-        while let Some(packet) = connection.next().await {
+        let _ = self.status.send(MonitorStatus::Connected {
+            streamer: streamer.to_string(),
+            since: Local::now(),
+        });
+        loop {
+            let packet = tokio::select! {
+                _ = self.reconnect.notified() => return Ok(()),
+                Ok(()) = self.options.changed() => {
+                    RoomInfo::write_options(long_room_id, self.options.borrow().clone());
+                    continue;
+                }
+                packet = connection.next() => match packet {
+                    Some(packet) => packet,
+                    None => break,
+                },
+            };
             match packet {
                 Ok(packet) => {
                     debug!("received packet: {}", packet.operation);
@@ -96,7 +192,7 @@ impl Monitor {
                 }
                 Err(e) => {
                     error!("receive packet error: {:?}", e);
-                    return Err(e.into());
+                    return Err(e);
                 }
             }
         }
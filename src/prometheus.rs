@@ -0,0 +1,370 @@
+//! 以 prometheus 格式暴露各个直播间的实时指标
+//!
+//! 和 [`InfluxAppender`](crate::influx::InfluxAppender) 一样订阅 packet 通道，
+//! 但只在内存里保留每个房间的当前值，由 `/metrics` 接口读取。
+
+use std::{
+    collections::{HashMap, VecDeque},
+    convert::Infallible,
+    fmt::Write,
+    net::SocketAddr,
+    sync::{atomic::Ordering, Arc},
+};
+
+use anyhow::{Context, Result};
+use biliapi::ws_protocol::{KnownOperation, Operation, Packet};
+use chrono::{DateTime, Local};
+use hyper::{
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use parking_lot::RwLock;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::{
+    influx::{messages::*, GuardMerger, RoomInfo, SuperChatCache},
+    revenue::Money,
+};
+
+/// 弹幕速率的统计窗口（秒）
+const DANMU_WINDOW_SECS: i64 = 10;
+
+type Metrics = Arc<RwLock<HashMap<u64, RoomMetrics>>>;
+
+/// 单个房间的指标
+#[derive(Debug, Default)]
+struct RoomMetrics {
+    streamer: String,
+    popularity: i64,
+    /// 最近 DANMU_WINDOW_SECS 秒内每秒的弹幕数
+    danmu_window: VecDeque<(i64, u64)>,
+    danmu_total: u64,
+    /// 启动以来的营收（元），SC 被删除时减去
+    revenue_total: Money,
+    guard_total: u64,
+    /// 没收到过 LIVE / PREPARING 时为 None
+    live: Option<bool>,
+}
+
+impl RoomMetrics {
+    fn count_danmu(&mut self, secs: i64) {
+        self.danmu_total += 1;
+        match self.danmu_window.back_mut() {
+            Some((t, cnt)) if *t == secs => *cnt += 1,
+            _ => self.danmu_window.push_back((secs, 1)),
+        }
+        while let Some(&(t, _)) = self.danmu_window.front() {
+            if t > secs - DANMU_WINDOW_SECS {
+                break;
+            }
+            self.danmu_window.pop_front();
+        }
+    }
+
+    fn danmu_per_sec(&self, now: i64) -> f64 {
+        let count: u64 = self
+            .danmu_window
+            .iter()
+            .filter(|(t, _)| *t > now - DANMU_WINDOW_SECS)
+            .map(|(_, cnt)| cnt)
+            .sum();
+        count as f64 / DANMU_WINDOW_SECS as f64
+    }
+}
+
+pub struct PrometheusExporter {
+    addr: SocketAddr,
+    /// 接收直播的 packet
+    packets_receiver: broadcast::Receiver<Packet>,
+    metrics: Metrics,
+    /// 合并同一次上舰的几条消息
+    guards: GuardMerger,
+    /// 最近的 SC，被删除时从营收里减去
+    super_chats: SuperChatCache,
+}
+
+impl PrometheusExporter {
+    pub fn new(addr: SocketAddr, packets_receiver: broadcast::Receiver<Packet>) -> Self {
+        Self {
+            addr,
+            packets_receiver,
+            metrics: Default::default(),
+            guards: GuardMerger::default(),
+            super_chats: SuperChatCache::default(),
+        }
+    }
+
+    /// 启动 http 服务，直到 packet 通道关闭
+    pub async fn start(mut self) -> Result<()> {
+        let metrics = self.metrics.clone();
+        let make_svc = make_service_fn(move |_conn| {
+            let metrics = metrics.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req| {
+                    let metrics = metrics.clone();
+                    async move { Ok::<_, Infallible>(Self::handle(req, &metrics)) }
+                }))
+            }
+        });
+        let server = Server::try_bind(&self.addr)
+            .context("failed to bind prometheus exporter")?
+            .serve(make_svc);
+        info!("prometheus exporter listening on {}", self.addr);
+
+        tokio::select! {
+            ret = server => {
+                error!("prometheus server stopped unexpectedly: {:?}", ret);
+                ret?;
+            }
+            _ = self.start_consumer() => {}
+        }
+        Ok(())
+    }
+
+    async fn start_consumer(&mut self) {
+        loop {
+            match self.packets_receiver.recv().await {
+                Ok(packet) => {
+                    if let Err(e) = self.process_packet(packet) {
+                        warn!("prometheus exporter failed to process packet: {:?}", e);
+                    }
+                }
+                Err(RecvError::Lagged(cnt)) => {
+                    error!("prometheus exporter lagged {} packets!", cnt);
+                    continue;
+                }
+                Err(RecvError::Closed) => {
+                    info!("packet publisher closed. prometheus exporter stop.");
+                    return;
+                }
+            }
+        }
+    }
+
+    fn process_packet(&mut self, packet: Packet) -> Result<()> {
+        let room_id = packet.room_id;
+        for guard in self.guards.flush(packet.time) {
+            let mut room = self.room(guard.room_id);
+            room.revenue_total += guard.event.price;
+            room.guard_total += 1;
+        }
+        match packet.operation {
+            Operation::Known(KnownOperation::SendMsgReply) => {
+                let msg = serde_json::from_str::<SendMsgReply>(&packet.body)
+                    .context("转换 SendMsgReply 失败")?;
+                self.on_send_msg_reply(msg, room_id, packet.time)
+            }
+            Operation::Known(KnownOperation::HeartbeatReply) => {
+                let popularity = Popularity::new(packet.body.parse()?);
+                self.room(room_id).popularity = popularity.value;
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn on_send_msg_reply(
+        &mut self,
+        msg: SendMsgReply,
+        room_id: u64,
+        t: DateTime<Local>,
+    ) -> Result<()> {
+        match msg.cmd.as_str() {
+            "SUPER_CHAT_MESSAGE" => {
+                let sc: SuperChat =
+                    serde_json::from_value(msg.data).context("convert msg to super chat failed")?;
+                self.room(room_id).revenue_total += sc.price();
+                self.super_chats.insert(sc);
+            }
+            "SUPER_CHAT_MESSAGE_DELETE" => {
+                let delete: SuperChatDelete = serde_json::from_value(msg.data)
+                    .context("convert msg to SuperChatDelete failed")?;
+                for id in delete.ids {
+                    if let Some(sc) = self.super_chats.remove(id) {
+                        self.room(room_id).revenue_total += -sc.price();
+                    }
+                }
+            }
+            "SEND_GIFT" => {
+                let gift: SendGift =
+                    serde_json::from_value(msg.data).context("convert msg to send gift failed")?;
+                if let Some(guard) = gift.guard_event() {
+                    self.guards.push(room_id, guard, t);
+                } else if !gift.is_free() {
+                    // 连麦时送给另一个监控中的主播，算在对方的直播间
+                    let receiver = gift
+                        .receiver_room_id()
+                        .filter(|&id| RoomInfo::from_cache_opt(id).is_some())
+                        .unwrap_or(room_id);
+                    self.room(receiver).revenue_total += gift.paid_price();
+                }
+            }
+            "USER_TOAST_MSG" => {
+                let guard: UserToastMsg = serde_json::from_value(msg.data)
+                    .context("convert msg to UserToastMsg failed.")?;
+                self.guards.push(room_id, guard.into(), t);
+            }
+            "GUARD_BUY" => {
+                let guard: GuardBuy =
+                    serde_json::from_value(msg.data).context("convert msg to GuardBuy failed.")?;
+                self.guards.push(room_id, guard.into(), t);
+            }
+            "LIVE" => self.room(room_id).live = Some(true),
+            "PREPARING" => self.room(room_id).live = Some(false),
+            cmd if cmd.starts_with("DANMU_MSG") => self.room(room_id).count_danmu(t.timestamp()),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn room(&self, room_id: u64) -> parking_lot::MappedRwLockWriteGuard<'_, RoomMetrics> {
+        parking_lot::RwLockWriteGuard::map(self.metrics.write(), |metrics| {
+            metrics.entry(room_id).or_insert_with(|| RoomMetrics {
+                streamer: RoomInfo::from_cache(room_id).streamer,
+                ..Default::default()
+            })
+        })
+    }
+
+    fn handle(req: Request<Body>, metrics: &Metrics) -> Response<Body> {
+        match (req.method(), req.uri().path()) {
+            (&Method::GET, "/metrics") => Response::new(Body::from(Self::render(metrics))),
+            _ => {
+                let mut resp = Response::new(Body::from("not found"));
+                *resp.status_mut() = StatusCode::NOT_FOUND;
+                resp
+            }
+        }
+    }
+
+    /// 输出 prometheus text format
+    fn render(metrics: &Metrics) -> String {
+        let metrics = metrics.read();
+        let now = Local::now().timestamp();
+        let mut rooms: Vec<_> = metrics.iter().collect();
+        rooms.sort_by_key(|(id, _)| **id);
+
+        let mut out = String::new();
+        write_family(
+            &mut out,
+            &rooms,
+            ("ddpanel_room_popularity", "gauge"),
+            "Current popularity of the live room.",
+            |r| Some(r.popularity as f64),
+        );
+        write_family(
+            &mut out,
+            &rooms,
+            ("ddpanel_room_danmu_per_second", "gauge"),
+            "Danmu per second averaged over the last 10 seconds.",
+            |r| Some(r.danmu_per_sec(now)),
+        );
+        write_family(
+            &mut out,
+            &rooms,
+            ("ddpanel_room_danmu_total", "counter"),
+            "Danmu received since start.",
+            |r| Some(r.danmu_total as f64),
+        );
+        write_family(
+            &mut out,
+            &rooms,
+            ("ddpanel_room_revenue_yuan_total", "gauge"),
+            "Revenue (gifts, super chats and guards) in RMB since start, minus deleted super chats.",
+            |r| Some(r.revenue_total.yuan()),
+        );
+        write_family(
+            &mut out,
+            &rooms,
+            ("ddpanel_room_guard_purchases_total", "counter"),
+            "Guard purchases since start.",
+            |r| Some(r.guard_total as f64),
+        );
+        write_family(
+            &mut out,
+            &rooms,
+            ("ddpanel_room_live", "gauge"),
+            "Whether the room is live (1) or not (0).",
+            |r| r.live.map(|live| if live { 1.0 } else { 0.0 }),
+        );
+        let authenticated = crate::spider::cookies::AUTHENTICATED.load(Ordering::Relaxed);
+        let _ = writeln!(
+            out,
+            "# HELP ddpanel_spider_authenticated Whether the spider is logged in (1) or not (0)."
+        );
+        let _ = writeln!(out, "# TYPE ddpanel_spider_authenticated gauge");
+        let _ = writeln!(
+            out,
+            "ddpanel_spider_authenticated {}",
+            if authenticated { 1 } else { 0 }
+        );
+        out
+    }
+}
+
+/// 输出一个指标的所有房间
+fn write_family(
+    out: &mut String,
+    rooms: &[(&u64, &RoomMetrics)],
+    (name, kind): (&str, &str),
+    help: &str,
+    value: impl Fn(&RoomMetrics) -> Option<f64>,
+) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+    for (room_id, room) in rooms {
+        if let Some(v) = value(*room) {
+            let _ = writeln!(
+                out,
+                "{}{{room_id=\"{}\",streamer=\"{}\"}} {}",
+                name,
+                room_id,
+                escape_label(&room.streamer),
+                v
+            );
+        }
+    }
+}
+
+fn escape_label(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{packet, super_chat};
+
+    fn exporter() -> PrometheusExporter {
+        let (_, packets) = broadcast::channel(16);
+        PrometheusExporter::new(SocketAddr::from(([127, 0, 0, 1], 0)), packets)
+    }
+
+    fn delete(room_id: u64, ids: &[u64]) -> Packet {
+        packet(
+            room_id,
+            serde_json::json!({"cmd": "SUPER_CHAT_MESSAGE_DELETE", "data": {"ids": ids}}),
+        )
+    }
+
+    fn revenue(exporter: &PrometheusExporter, room_id: u64) -> f64 {
+        exporter.metrics.read()[&room_id].revenue_total.yuan()
+    }
+
+    #[test]
+    fn deleted_super_chat_is_subtracted() {
+        let mut exporter = exporter();
+        exporter.process_packet(super_chat(42001, 1, 30)).unwrap();
+        exporter.process_packet(super_chat(42001, 2, 50)).unwrap();
+        assert_eq!(revenue(&exporter, 42001), 80.0);
+
+        // 找不到的 id 不影响
+        exporter.process_packet(delete(42001, &[1, 3])).unwrap();
+        assert_eq!(revenue(&exporter, 42001), 50.0);
+        // 重复删除只减一次
+        exporter.process_packet(delete(42001, &[1])).unwrap();
+        assert_eq!(revenue(&exporter, 42001), 50.0);
+    }
+}
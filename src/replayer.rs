@@ -1,6 +1,5 @@
 use anyhow::{Context, Result};
 use biliapi::ws_protocol::Packet;
-use reqwest::Client as HttpClient;
 use std::time::Duration;
 use tokio::{
     fs::File,
@@ -8,21 +7,23 @@ use tokio::{
     sync::broadcast,
 };
 
+use crate::http::BiliHttp;
+
 pub struct FileReplayer {
     broadcaster: broadcast::Sender<Packet>,
-    http_client: HttpClient,
+    http: BiliHttp,
     replay_delay: Duration,
 }
 
 impl FileReplayer {
     pub async fn new(
         broadcaster: broadcast::Sender<Packet>,
-        http_client: HttpClient,
+        http: BiliHttp,
         replay_delay_ms: u32,
     ) -> Result<Self> {
         Ok(Self {
             broadcaster,
-            http_client,
+            http,
             replay_delay: Duration::from_millis(replay_delay_ms as u64),
         })
     }
@@ -62,10 +63,17 @@ impl FileReplayer {
             use biliapi::Request;
 
             if RoomInfo::from_cache_opt(packet.room_id).is_none() {
-                let info =
-                    biliapi::requests::InfoByRoom::request(&self.http_client, packet.room_id)
-                        .await?;
-                RoomInfo::write_cache(packet.room_id, info.anchor_info.base.uname);
+                let room_id = packet.room_id;
+                let info = self
+                    .http
+                    .request(|client| async move {
+                        biliapi::requests::InfoByRoom::request(&client, room_id).await
+                    })
+                    .await?;
+                RoomInfo::write_cache(RoomInfo {
+                    anchor_uid: Some(info.room_info.uid),
+                    ..RoomInfo::new(packet.room_id, info.anchor_info.base.uname)
+                });
             }
 
             cnt += 1;
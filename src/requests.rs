@@ -0,0 +1,486 @@
+//! biliapi 里没有的接口
+
+use std::collections::HashMap;
+
+use anyhow::{Context, Result};
+use reqwest::Method;
+use serde::de::DeserializeOwned;
+
+use crate::http::{ApiError, BiliHttp};
+
+/// b 站接口统一的返回格式
+#[derive(Debug, Deserialize)]
+struct BiliResponse<T> {
+    code: i64,
+    #[serde(default)]
+    message: String,
+    data: Option<T>,
+}
+
+/// GET 一个 b 站接口并取出 data
+pub async fn get<T: DeserializeOwned>(
+    http: &BiliHttp,
+    url: &str,
+    query: &[(&str, String)],
+) -> Result<T> {
+    call(http, Method::GET, url, query)
+        .await?
+        .context("no data in response")
+}
+
+/// POST 表单到一个 b 站接口并取出 data
+pub async fn post<T: DeserializeOwned>(
+    http: &BiliHttp,
+    url: &str,
+    form: &[(&str, String)],
+) -> Result<T> {
+    call(http, Method::POST, url, form)
+        .await?
+        .context("no data in response")
+}
+
+/// 发出请求并检查 code，有的接口成功时没有 data
+async fn call<T: DeserializeOwned>(
+    http: &BiliHttp,
+    method: Method,
+    url: &str,
+    params: &[(&str, String)],
+) -> Result<Option<T>> {
+    let request_url = http.url(url)?;
+    http.request(|client| async move {
+        let request = if method == Method::GET {
+            client.get(request_url).query(params)
+        } else {
+            client.request(method, request_url).form(params)
+        };
+        let text = request.send().await?.error_for_status()?.text().await?;
+        let resp: BiliResponse<T> = serde_json::from_str(&text)
+            .with_context(|| format!("failed to parse response of {}", url))?;
+        if resp.code != 0 {
+            return Err(ApiError {
+                url: url.to_string(),
+                code: resp.code,
+                message: resp.message,
+            }
+            .into());
+        }
+        Ok(resp.data)
+    })
+    .await
+}
+
+/// 分区的直播间列表，按人气排序
+#[derive(Debug, Deserialize)]
+pub struct AreaRoomList {
+    pub list: Vec<AreaRoom>,
+    #[serde(default)]
+    pub has_more: u8,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct AreaRoom {
+    #[serde(rename = "roomid")]
+    pub room_id: u64,
+    pub uid: u64,
+    pub uname: String,
+    /// 人气值
+    pub online: u64,
+}
+
+impl AreaRoomList {
+    pub async fn request(
+        http: &BiliHttp,
+        parent_area_id: u64,
+        area_id: u64,
+        page: u32,
+    ) -> Result<Self> {
+        get(
+            http,
+            "https://api.live.bilibili.com/xlive/web-interface/v1/second/getList",
+            &[
+                ("platform", "web".to_string()),
+                ("parent_area_id", parent_area_id.to_string()),
+                ("area_id", area_id.to_string()),
+                ("sort_type", "online".to_string()),
+                ("page", page.to_string()),
+            ],
+        )
+        .await
+    }
+}
+
+/// 直播间的基本状态，短号也可以
+#[derive(Debug, Deserialize, Clone)]
+pub struct RoomInit {
+    pub room_id: u64,
+    pub uid: u64,
+    /// 0 未开播，1 直播中，2 轮播
+    pub live_status: u8,
+}
+
+impl RoomInit {
+    pub async fn request(http: &BiliHttp, room_id: u64) -> Result<Self> {
+        get(
+            http,
+            "https://api.live.bilibili.com/room/v1/Room/room_init",
+            &[("id", room_id.to_string())],
+        )
+        .await
+    }
+}
+
+/// 批量按主播 uid 查询直播状态
+#[derive(Debug, Deserialize)]
+pub struct StatusInfoByUids(pub HashMap<String, RoomStatusInfo>);
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct RoomStatusInfo {
+    pub room_id: u64,
+    pub uid: u64,
+    /// 0 未开播，1 直播中，2 轮播
+    pub live_status: u8,
+}
+
+impl StatusInfoByUids {
+    pub async fn request(http: &BiliHttp, uids: &[u64]) -> Result<Self> {
+        let query: Vec<_> = uids.iter().map(|uid| ("uids[]", uid.to_string())).collect();
+        get(
+            http,
+            "https://api.live.bilibili.com/room/v1/Room/get_status_info_by_uids",
+            &query,
+        )
+        .await
+    }
+}
+
+/// up 主投稿的视频列表，按发布时间倒序
+#[derive(Debug, Deserialize)]
+pub struct SpaceVideos {
+    pub list: SpaceVideoList,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SpaceVideoList {
+    #[serde(default)]
+    pub vlist: Vec<SpaceVideo>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct SpaceVideo {
+    pub bvid: String,
+    pub title: String,
+    /// 发布时间（unix 秒）
+    pub created: i64,
+}
+
+impl SpaceVideos {
+    pub async fn request(http: &BiliHttp, mid: u64, page_size: u32) -> Result<Self> {
+        get(
+            http,
+            "https://api.bilibili.com/x/space/arc/search",
+            &[
+                ("mid", mid.to_string()),
+                ("ps", page_size.to_string()),
+                ("pn", "1".to_string()),
+                ("order", "pubdate".to_string()),
+            ],
+        )
+        .await
+    }
+}
+
+/// 单个视频的统计
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ArchiveStat {
+    pub view: u64,
+    pub danmaku: u64,
+    pub reply: u64,
+    pub favorite: u64,
+    pub coin: u64,
+    pub share: u64,
+    pub like: u64,
+}
+
+impl ArchiveStat {
+    pub async fn request(http: &BiliHttp, bvid: &str) -> Result<Self> {
+        get(
+            http,
+            "https://api.bilibili.com/x/web-interface/archive/stat",
+            &[("bvid", bvid.to_string())],
+        )
+        .await
+    }
+}
+
+/// 直播间的大航海列表，一页
+#[derive(Debug, Deserialize)]
+pub struct GuardTopList {
+    pub info: GuardListInfo,
+    #[serde(default)]
+    pub top3: Vec<GuardMember>,
+    #[serde(default)]
+    pub list: Vec<GuardMember>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GuardListInfo {
+    /// 舰长总数
+    pub num: u64,
+    /// 总页数
+    pub page: u32,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct GuardMember {
+    pub uid: u64,
+    /// 1 总督，2 提督，3 舰长
+    pub guard_level: u8,
+}
+
+impl GuardTopList {
+    pub const PAGE_SIZE: u32 = 29;
+
+    pub async fn request(http: &BiliHttp, room_id: u64, ruid: u64, page: u32) -> Result<Self> {
+        get(
+            http,
+            "https://api.live.bilibili.com/xlive/app-room/v2/guardTab/topList",
+            &[
+                ("roomid", room_id.to_string()),
+                ("ruid", ruid.to_string()),
+                ("page", page.to_string()),
+                ("page_size", Self::PAGE_SIZE.to_string()),
+            ],
+        )
+        .await
+    }
+}
+
+/// 粉丝团，只取人数
+#[derive(Debug, Deserialize)]
+pub struct FansMembersRank {
+    pub num: u64,
+}
+
+impl FansMembersRank {
+    pub async fn request(http: &BiliHttp, ruid: u64) -> Result<Self> {
+        get(
+            http,
+            "https://api.live.bilibili.com/xlive/general-interface/v1/rank/getFansMembersRank",
+            &[
+                ("ruid", ruid.to_string()),
+                ("page", "1".to_string()),
+                ("page_size", "1".to_string()),
+            ],
+        )
+        .await
+    }
+}
+
+/// 直播间的标题、分区等，会经常变化
+#[derive(Debug, Deserialize, Clone)]
+pub struct RoomBaseInfo {
+    pub room_id: u64,
+    pub uid: u64,
+    pub title: String,
+    pub area_id: u64,
+    pub area_name: String,
+    pub parent_area_id: u64,
+    pub parent_area_name: String,
+    #[serde(default)]
+    pub user_cover: String,
+    /// 0 未开播，1 直播中，2 轮播
+    pub live_status: u8,
+    /// 开播时间，未开播时为 `0000-00-00 00:00:00`
+    #[serde(default)]
+    pub live_time: String,
+}
+
+impl RoomBaseInfo {
+    pub async fn request(http: &BiliHttp, room_id: u64) -> Result<Self> {
+        get(
+            http,
+            "https://api.live.bilibili.com/room/v1/Room/get_info",
+            &[("room_id", room_id.to_string())],
+        )
+        .await
+    }
+}
+
+/// 当前登录状态，未登录时接口返回 -101
+#[derive(Debug, Deserialize)]
+pub struct Nav {
+    #[serde(rename = "isLogin")]
+    pub is_login: bool,
+    #[serde(default)]
+    pub uname: String,
+}
+
+impl Nav {
+    pub async fn request(http: &BiliHttp) -> Result<Self> {
+        let url = http.url("https://api.bilibili.com/x/web-interface/nav")?;
+        http.request(|client| async move {
+            let text = client
+                .get(url.clone())
+                .send()
+                .await?
+                .error_for_status()?
+                .text()
+                .await?;
+            let resp: BiliResponse<Nav> = serde_json::from_str(&text)?;
+            match resp.data {
+                Some(nav) => Ok::<_, anyhow::Error>(nav),
+                None if resp.code == -101 => Ok(Nav {
+                    is_login: false,
+                    uname: String::new(),
+                }),
+                None => Err(ApiError {
+                    url: url.to_string(),
+                    code: resp.code,
+                    message: resp.message,
+                }
+                .into()),
+            }
+        })
+        .await
+    }
+}
+
+/// cookie 是否需要刷新
+#[derive(Debug, Deserialize)]
+pub struct CookieInfo {
+    pub refresh: bool,
+    /// 服务器当前的毫秒时间戳，用来生成刷新页面的路径
+    pub timestamp: i64,
+}
+
+impl CookieInfo {
+    pub async fn request(http: &BiliHttp, csrf: &str) -> Result<Self> {
+        get(
+            http,
+            "https://passport.bilibili.com/x/passport-login/web/cookie/info",
+            &[("csrf", csrf.to_string())],
+        )
+        .await
+    }
+}
+
+/// 刷新页面里的 refresh_csrf
+#[derive(Debug)]
+pub struct RefreshCsrf(pub String);
+
+impl RefreshCsrf {
+    /// `correspond_path` 是用公钥加密 `refresh_{timestamp}` 后的 hex
+    pub async fn request(http: &BiliHttp, correspond_path: &str) -> Result<Self> {
+        lazy_static::lazy_static! {
+            static ref REFRESH_CSRF: regex::Regex =
+                regex::Regex::new(r#"<div id="1-name">([^<]+)</div>"#).unwrap();
+        }
+        let url = http.url(&format!(
+            "https://www.bilibili.com/correspond/1/{}",
+            correspond_path
+        ))?;
+        let html = http
+            .request(|client| async move {
+                client
+                    .get(url)
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .text()
+                    .await
+            })
+            .await?;
+        let csrf = REFRESH_CSRF
+            .captures(&html)
+            .map(|c| c[1].trim().to_string())
+            .context("refresh_csrf not found in correspond page")?;
+        Ok(Self(csrf))
+    }
+}
+
+/// 刷新 cookie，新的 cookie 由响应头下发
+#[derive(Debug, Deserialize)]
+pub struct CookieRefresh {
+    pub refresh_token: String,
+}
+
+impl CookieRefresh {
+    pub async fn request(
+        http: &BiliHttp,
+        csrf: &str,
+        refresh_csrf: &str,
+        refresh_token: &str,
+    ) -> Result<Self> {
+        post(
+            http,
+            "https://passport.bilibili.com/x/passport-login/web/cookie/refresh",
+            &[
+                ("csrf", csrf.to_string()),
+                ("refresh_csrf", refresh_csrf.to_string()),
+                ("source", "main_web".to_string()),
+                ("refresh_token", refresh_token.to_string()),
+            ],
+        )
+        .await
+    }
+}
+
+/// 用新 cookie 确认刷新，旧的 refresh_token 随之失效
+pub struct ConfirmRefresh;
+
+impl ConfirmRefresh {
+    pub async fn request(http: &BiliHttp, csrf: &str, old_refresh_token: &str) -> Result<()> {
+        call::<serde_json::Value>(
+            http,
+            Method::POST,
+            "https://passport.bilibili.com/x/passport-login/web/confirm/refresh",
+            &[
+                ("csrf", csrf.to_string()),
+                ("refresh_token", old_refresh_token.to_string()),
+            ],
+        )
+        .await?;
+        Ok(())
+    }
+}
+
+/// 申请登录二维码
+#[derive(Debug, Deserialize)]
+pub struct QrCodeGenerate {
+    pub url: String,
+    pub qrcode_key: String,
+}
+
+impl QrCodeGenerate {
+    pub async fn request(http: &BiliHttp) -> Result<Self> {
+        get(
+            http,
+            "https://passport.bilibili.com/x/passport-login/web/qrcode/generate",
+            &[],
+        )
+        .await
+    }
+}
+
+/// 扫码结果，登录成功时 cookie 由响应头下发
+#[derive(Debug, Deserialize)]
+pub struct QrCodePoll {
+    /// 0 成功，86101 未扫码，86090 已扫码未确认，86038 二维码已失效
+    pub code: i64,
+    #[serde(default)]
+    pub message: String,
+    /// 刷新 cookie 用，只在登录成功时有
+    #[serde(default)]
+    pub refresh_token: String,
+}
+
+impl QrCodePoll {
+    pub async fn request(http: &BiliHttp, qrcode_key: &str) -> Result<Self> {
+        get(
+            http,
+            "https://passport.bilibili.com/x/passport-login/web/qrcode/poll",
+            &[("qrcode_key", qrcode_key.to_string())],
+        )
+        .await
+    }
+}
@@ -0,0 +1,124 @@
+//! 营收的统一计算
+//!
+//! 礼物的价格是金瓜子（1000 金瓜子 = 1 元），SC 是元，电池是 0.1 元。
+//! 这里统一换算为 [`Money`]，内部用整数的金瓜子计算，写入时再转换为元。
+//! 所有 `live-gift` 点的 `price` 都是元，设置了主播分成时另有 `share` field。
+
+use std::{
+    fmt::{self, Display, Formatter},
+    iter::Sum,
+    ops::{Add, AddAssign, Div, Mul, Neg},
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use influxdb_client::Point;
+
+/// 金瓜子每元
+const GOLD_PER_YUAN: i64 = 1000;
+/// 金瓜子每电池
+const GOLD_PER_BATTERY: i64 = 100;
+
+/// 金额，单位为金瓜子
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Money(i64);
+
+impl Money {
+    pub const ZERO: Money = Money(0);
+
+    pub fn from_gold(gold: u64) -> Self {
+        Money(gold as i64)
+    }
+
+    pub fn from_yuan(yuan: u64) -> Self {
+        Money(yuan as i64 * GOLD_PER_YUAN)
+    }
+
+    /// 电池数，不足一个电池的部分舍去
+    pub fn batteries(self) -> i64 {
+        self.0 / GOLD_PER_BATTERY
+    }
+
+    /// 元，只在写入和显示时使用
+    pub fn yuan(self) -> f64 {
+        self.0 as f64 / GOLD_PER_YUAN as f64
+    }
+
+    /// 按设置的分成比例主播实际得到的部分，只计整数个电池
+    pub fn streamer_share(self) -> Option<Money> {
+        let percent = STREAMER_SHARE.load(Ordering::Relaxed);
+        if percent == 0 {
+            return None;
+        }
+        let share = Money(self.0 * percent as i64 / 100);
+        Some(Money(share.batteries() * GOLD_PER_BATTERY))
+    }
+
+    /// 加上 price field，设置了分成时加上 share field
+    pub fn add_fields(self, pt: Point) -> Point {
+        let pt = pt.field("price", self.yuan());
+        match self.streamer_share() {
+            Some(share) => pt.field("share", share.yuan()),
+            None => pt,
+        }
+    }
+}
+
+impl Add for Money {
+    type Output = Money;
+
+    fn add(self, rhs: Money) -> Money {
+        Money(self.0 + rhs.0)
+    }
+}
+impl AddAssign for Money {
+    fn add_assign(&mut self, rhs: Money) {
+        self.0 += rhs.0;
+    }
+}
+impl Mul<u32> for Money {
+    type Output = Money;
+
+    fn mul(self, rhs: u32) -> Money {
+        Money(self.0 * rhs as i64)
+    }
+}
+/// 整数除法，用于从总价算单价
+impl Div<u32> for Money {
+    type Output = Money;
+
+    fn div(self, rhs: u32) -> Money {
+        Money(self.0 / rhs.max(1) as i64)
+    }
+}
+impl Neg for Money {
+    type Output = Money;
+
+    fn neg(self) -> Money {
+        Money(-self.0)
+    }
+}
+impl Sum for Money {
+    fn sum<I: Iterator<Item = Money>>(iter: I) -> Money {
+        iter.fold(Money::ZERO, Add::add)
+    }
+}
+impl Display for Money {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let gold = self.0.abs();
+        f.write_fmt(format_args!(
+            "￥{}{}.{:03}",
+            sign,
+            gold / GOLD_PER_YUAN,
+            gold % GOLD_PER_YUAN
+        ))
+    }
+}
+
+/// 主播分成的百分比，0 为不计算
+static STREAMER_SHARE: AtomicU32 = AtomicU32::new(0);
+
+/// 设置主播分成的百分比，启动时设置一次
+pub fn set_streamer_share(percent: u32) {
+    STREAMER_SHARE.store(percent.min(100), Ordering::Relaxed);
+}
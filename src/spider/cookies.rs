@@ -0,0 +1,265 @@
+//! 爬虫的 cookie 持久化
+//!
+//! cookie 由 `ddpanel-cli login` 扫码得到，和 refresh_token 一起存在同一个文件里。
+//! 运行时定期检查登录状态，服务器要求刷新时用 refresh_token 换一组新 cookie，
+//! 每次都把最新的 cookie 写回文件。未登录时给出提示。
+
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use anyhow::*;
+use cookie_store::CookieStore;
+use reqwest_cookie_store::CookieStoreMutex;
+
+use crate::{
+    http::BiliHttp,
+    requests::{ConfirmRefresh, CookieInfo, CookieRefresh, Nav, RefreshCsrf},
+};
+
+/// 检查登录状态的间隔
+const CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// 爬虫当前是否已登录，prometheus 会输出
+pub static AUTHENTICATED: AtomicBool = AtomicBool::new(false);
+
+/// 刷新 cookie 时加密路径用的公钥
+const REFRESH_PUBLIC_KEY: &str = "-----BEGIN PUBLIC KEY-----
+MIGfMA0GCSqGSIb3DQEBAQUAA4GNADCBiQKBgQDLgd2OAkcGVtoE3ThUREbio0EgUc/prcajMKXvkCKFCWhJYJcLkcM2DKKcSeFpD/j6Boy538YXnR6VhcuUJOhH2x71nzPjfdTcqMz7djHum0qSZA0AyCBDABUqCrfNgCiJ00Ra7GmRj+YCK1NJEuewlb40JNrRuoEUXpabUzGB8QIDAQAB
+-----END PUBLIC KEY-----";
+
+/// cookie 文件的格式
+#[derive(Debug, Serialize, Deserialize)]
+struct CookieFile {
+    #[serde(default)]
+    refresh_token: Option<String>,
+    /// cookie_store 序列化的 cookie，一个一项
+    cookies: Vec<serde_json::Value>,
+}
+
+/// 从文件解析出 CookieStore 和 refresh_token
+///
+/// 兼容旧版只有 cookie_store 格式的文件，这时没有 refresh_token
+pub fn load(path: impl AsRef<Path>) -> Result<(CookieStore, Option<String>)> {
+    let content = std::fs::read_to_string(path.as_ref())?;
+    let (cookies, refresh_token) = match serde_json::from_str::<CookieFile>(&content) {
+        Ok(file) => {
+            let mut lines = String::new();
+            for cookie in file.cookies {
+                lines.push_str(&serde_json::to_string(&cookie)?);
+                lines.push('\n');
+            }
+            (lines, file.refresh_token)
+        }
+        Err(_) => (content, None),
+    };
+    let cookies = CookieStore::load_json(cookies.as_bytes()).map_err(|e| anyhow!(e))?;
+    Ok((cookies, refresh_token))
+}
+
+/// 先写临时文件再改名，避免写到一半时退出损坏 cookie 文件，
+/// cookie 和 refresh_token 总是一起更新
+pub fn save(
+    path: impl AsRef<Path>,
+    cookies: &CookieStore,
+    refresh_token: Option<&str>,
+) -> Result<()> {
+    let path = path.as_ref();
+    let mut buf = vec![];
+    cookies
+        .save_json(&mut buf)
+        .map_err(|e| anyhow!("failed to serialize cookies: {}", e))?;
+    let cookies = String::from_utf8(buf)?
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(serde_json::from_str)
+        .collect::<Result<_, _>>()?;
+    let file = CookieFile {
+        refresh_token: refresh_token.map(str::to_string),
+        cookies,
+    };
+
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, serde_json::to_vec_pretty(&file)?)?;
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+/// 用公钥加密 `refresh_{timestamp}`，得到刷新页面的路径
+fn correspond_path(timestamp: i64) -> Result<String> {
+    use rsa::{pkcs8::DecodePublicKey, Oaep, RsaPublicKey};
+
+    let key = RsaPublicKey::from_public_key_pem(REFRESH_PUBLIC_KEY)
+        .map_err(|e| anyhow!("invalid refresh public key: {}", e))?;
+    let encrypted = key
+        .encrypt(
+            &mut rand::thread_rng(),
+            Oaep::new::<sha2::Sha256>(),
+            format!("refresh_{}", timestamp).as_bytes(),
+        )
+        .map_err(|e| anyhow!("failed to encrypt correspond path: {}", e))?;
+    Ok(encrypted.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+pub struct CookieKeeper {
+    path: PathBuf,
+    http: BiliHttp,
+    cookies: Arc<CookieStoreMutex>,
+    /// 旧版 cookie 文件里没有，只能重新登录
+    refresh_token: Option<String>,
+}
+
+impl CookieKeeper {
+    pub fn new(
+        path: PathBuf,
+        http: BiliHttp,
+        cookies: Arc<CookieStoreMutex>,
+        refresh_token: Option<String>,
+    ) -> Self {
+        Self {
+            path,
+            http,
+            cookies,
+            refresh_token,
+        }
+    }
+
+    /// 一直运行，出错时只打日志
+    pub async fn run(mut self) -> Result<()> {
+        loop {
+            if let Err(e) = self.check().await {
+                warn!("failed to check spider login status: {:?}", e);
+            }
+            tokio::time::sleep(CHECK_INTERVAL).await;
+        }
+    }
+
+    async fn check(&mut self) -> Result<()> {
+        let nav = Nav::request(&self.http).await?;
+        let was_authenticated = AUTHENTICATED.swap(nav.is_login, Ordering::Relaxed);
+        if !nav.is_login {
+            warn!(
+                "spider is running unauthenticated, run `ddpanel-cli login --cookie {}` to login.",
+                self.path.display()
+            );
+            return Ok(());
+        }
+        if !was_authenticated {
+            info!("spider logged in as {}", nav.uname);
+        }
+
+        if let Some(csrf) = self.csrf() {
+            let info = CookieInfo::request(&self.http, &csrf).await?;
+            if info.refresh {
+                match self.refresh_token.clone() {
+                    Some(refresh_token) => {
+                        self.refresh(&csrf, &refresh_token, info.timestamp)
+                            .await
+                            .context("failed to refresh spider cookies")?;
+                        info!("spider cookies refreshed");
+                    }
+                    None => warn!(
+                        "spider cookies are about to expire and cannot be refreshed, run `ddpanel-cli login --cookie {}` again.",
+                        self.path.display()
+                    ),
+                }
+            }
+        }
+
+        // 服务器可能在响应里更新了 cookie
+        self.persist()?;
+        debug!("spider cookies saved to {}", self.path.display());
+        Ok(())
+    }
+
+    /// 用 refresh_token 换新 cookie，成功后确认让旧 cookie 失效
+    async fn refresh(&mut self, csrf: &str, refresh_token: &str, timestamp: i64) -> Result<()> {
+        let path = correspond_path(timestamp)?;
+        let refresh_csrf = RefreshCsrf::request(&self.http, &path).await?;
+        let refreshed =
+            CookieRefresh::request(&self.http, csrf, &refresh_csrf.0, refresh_token).await?;
+        // 新 cookie 已经在 cookie store 里，确认前先落盘，
+        // 否则确认后退出会丢掉唯一有效的 refresh_token
+        self.refresh_token = Some(refreshed.refresh_token);
+        self.persist()?;
+
+        let new_csrf = self.csrf().context("no bili_jct cookie after refresh")?;
+        ConfirmRefresh::request(&self.http, &new_csrf, refresh_token).await
+    }
+
+    fn persist(&self) -> Result<()> {
+        let cookies = self
+            .cookies
+            .lock()
+            .map_err(|_| anyhow!("cookie store poisoned"))?;
+        save(&self.path, &cookies, self.refresh_token.as_deref())
+    }
+
+    fn csrf(&self) -> Option<String> {
+        let cookies = self.cookies.lock().ok()?;
+        let value = cookies
+            .get("bilibili.com", "/", "bili_jct")
+            .map(|c| c.value().to_string());
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("ddpanel-{}-{}.json", name, std::process::id()))
+    }
+
+    fn store() -> CookieStore {
+        let url = reqwest::Url::parse("https://www.bilibili.com/").unwrap();
+        let mut cookies = CookieStore::default();
+        cookies
+            .parse("bili_jct=abc; Domain=bilibili.com; Max-Age=3600", &url)
+            .unwrap();
+        cookies
+    }
+
+    #[test]
+    fn refresh_token_is_saved_with_cookies() {
+        let path = temp_path("cookies");
+        let cookies = store();
+        save(&path, &cookies, Some("token")).unwrap();
+
+        let (loaded, refresh_token) = load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(refresh_token.as_deref(), Some("token"));
+        assert_eq!(
+            loaded.get("bilibili.com", "/", "bili_jct").unwrap().value(),
+            "abc"
+        );
+    }
+
+    #[test]
+    fn legacy_file_has_no_refresh_token() {
+        let path = temp_path("legacy");
+        let cookies = store();
+        let mut buf = vec![];
+        cookies.save_json(&mut buf).unwrap();
+        std::fs::write(&path, buf).unwrap();
+
+        let (loaded, refresh_token) = load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(refresh_token, None);
+        assert!(loaded.get("bilibili.com", "/", "bili_jct").is_some());
+    }
+
+    #[test]
+    fn correspond_path_is_hex() {
+        let path = correspond_path(1684466082871).unwrap();
+        // 1024 位的 RSA 密钥
+        assert_eq!(path.len(), 256);
+        assert!(path.chars().all(|c| c.is_ascii_hexdigit()));
+    }
+}
@@ -0,0 +1,67 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use futures::future::{BoxFuture, FutureExt};
+
+use super::{JobSchedule, SpiderData, SpiderJob};
+use crate::{
+    http::BiliHttp,
+    influx::RoomInfo,
+    requests::{FansMembersRank, GuardTopList},
+    spider::GuardStat,
+};
+
+/// 每页之间的间隔
+const PAGE_INTERVAL: Duration = Duration::from_millis(300);
+
+/// 直播间的大航海人数（按等级）和粉丝团人数，只对有直播间的用户
+pub struct GuardJob;
+
+impl SpiderJob for GuardJob {
+    fn name(&self) -> &'static str {
+        "guard"
+    }
+
+    fn default_schedule(&self) -> JobSchedule {
+        JobSchedule {
+            enabled: true,
+            interval: 30 * 60,
+            pacing_ms: 2_000,
+        }
+    }
+
+    fn fetch<'a>(&'a self, http: &'a BiliHttp, uid: u64) -> BoxFuture<'a, Result<Vec<SpiderData>>> {
+        async move {
+            let room_id = match RoomInfo::from_anchor_uid(uid) {
+                Some(room) => room.id,
+                None => return Ok(vec![]),
+            };
+
+            let mut stat = GuardStat::default();
+            let mut page = 1;
+            loop {
+                let list = GuardTopList::request(http, room_id, uid, page).await?;
+                stat.total = list.info.num;
+                // 只有第一页有 top3
+                for member in list.top3.iter().chain(list.list.iter()) {
+                    match member.guard_level {
+                        1 => stat.governors += 1,
+                        2 => stat.admirals += 1,
+                        3 => stat.captains += 1,
+                        level => debug!("unknown guard level {}", level),
+                    }
+                }
+                if page >= list.info.page {
+                    break;
+                }
+                page += 1;
+                tokio::time::sleep(PAGE_INTERVAL).await;
+            }
+
+            tokio::time::sleep(PAGE_INTERVAL).await;
+            stat.fans_club = FansMembersRank::request(http, uid).await?.num;
+            Ok(vec![SpiderData::Guard(stat)])
+        }
+        .boxed()
+    }
+}
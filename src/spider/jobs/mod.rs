@@ -0,0 +1,130 @@
+//! 爬虫任务
+//!
+//! 每个任务对每个用户独立执行，有自己的间隔和错误处理。
+//! 新的数据源只需要实现 [`SpiderJob`] 并加入 [`all_jobs`]。
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use futures::future::BoxFuture;
+
+use super::SpiderData;
+use crate::http::BiliHttp;
+
+mod guard;
+mod room_info;
+mod uploader_stat;
+mod user_info;
+mod video_stat;
+
+pub use guard::GuardJob;
+pub use room_info::RoomInfoJob;
+pub use uploader_stat::UploaderStatJob;
+pub use user_info::UserInfoJob;
+pub use video_stat::VideoStatJob;
+
+/// 一个任务实际使用的调度
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JobSchedule {
+    pub enabled: bool,
+    /// 每轮的间隔（秒）
+    pub interval: u64,
+    /// 每个用户之间的间隔（毫秒）
+    pub pacing_ms: u64,
+}
+
+/// watch.toml 里的 `[spider.<job>]`，没写的项用任务的 [`SpiderJob::default_schedule`]
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq, Eq)]
+pub struct JobScheduleConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+    #[serde(
+        default,
+        deserialize_with = "positive",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub interval: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pacing_ms: Option<u64>,
+}
+
+impl JobScheduleConfig {
+    pub fn resolve(&self, default: JobSchedule) -> JobSchedule {
+        JobSchedule {
+            enabled: self.enabled.unwrap_or(default.enabled),
+            interval: self.interval.unwrap_or(default.interval),
+            pacing_ms: self.pacing_ms.unwrap_or(default.pacing_ms),
+        }
+    }
+}
+
+/// interval 为 0 时任务会不停地跑
+fn positive<'de, D>(deserializer: D) -> Result<Option<u64>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    use serde::de::{Deserialize, Error};
+
+    match Option::<u64>::deserialize(deserializer)? {
+        Some(0) => Err(D::Error::custom("interval must be positive")),
+        v => Ok(v),
+    }
+}
+
+pub trait SpiderJob: Send + Sync {
+    /// 任务名，即 watch.toml 里的 `[spider.<name>]`
+    fn name(&self) -> &'static str;
+
+    /// 没有配置时的调度
+    fn default_schedule(&self) -> JobSchedule {
+        JobSchedule {
+            enabled: true,
+            interval: 3 * 60,
+            pacing_ms: 1_000,
+        }
+    }
+
+    /// 爬取一个用户
+    fn fetch<'a>(&'a self, http: &'a BiliHttp, uid: u64) -> BoxFuture<'a, Result<Vec<SpiderData>>>;
+}
+
+/// 所有的爬虫任务
+pub fn all_jobs() -> Vec<Arc<dyn SpiderJob>> {
+    vec![
+        Arc::new(UserInfoJob),
+        Arc::new(UploaderStatJob),
+        Arc::new(VideoStatJob),
+        Arc::new(GuardJob),
+        Arc::new(RoomInfoJob),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn parse(content: &str) -> Result<HashMap<String, JobScheduleConfig>, toml::de::Error> {
+        toml::from_str(content)
+    }
+
+    #[test]
+    fn partial_table_uses_job_defaults() {
+        let schedules = parse("[guard]\npacing_ms = 500\n").unwrap();
+        let schedule = schedules["guard"].resolve(GuardJob.default_schedule());
+        assert_eq!(
+            schedule,
+            JobSchedule {
+                enabled: true,
+                interval: 30 * 60,
+                pacing_ms: 500,
+            }
+        );
+    }
+
+    #[test]
+    fn zero_interval_is_rejected() {
+        assert!(parse("[guard]\ninterval = 0\n").is_err());
+        assert!(parse("[guard]\ninterval = 1\n").is_ok());
+    }
+}
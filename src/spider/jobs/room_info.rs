@@ -0,0 +1,61 @@
+use anyhow::Result;
+use chrono::{Local, NaiveDateTime, TimeZone};
+use futures::future::{BoxFuture, FutureExt};
+
+use super::{JobSchedule, SpiderData, SpiderJob};
+use crate::{
+    http::BiliHttp,
+    influx::{RoomActivity, RoomInfo},
+    requests::RoomBaseInfo,
+    spider::RoomStat,
+};
+
+/// 直播间的标题、分区、封面和开播时间，只对有直播间的用户。
+/// 同时更新缓存里的标题和分区，礼物会带上这些 tag。
+pub struct RoomInfoJob;
+
+impl SpiderJob for RoomInfoJob {
+    fn name(&self) -> &'static str {
+        "room_info"
+    }
+
+    fn default_schedule(&self) -> JobSchedule {
+        JobSchedule {
+            enabled: true,
+            interval: 5 * 60,
+            pacing_ms: 1_000,
+        }
+    }
+
+    fn fetch<'a>(&'a self, http: &'a BiliHttp, uid: u64) -> BoxFuture<'a, Result<Vec<SpiderData>>> {
+        async move {
+            let room_id = match RoomInfo::from_anchor_uid(uid) {
+                Some(room) => room.id,
+                None => return Ok(vec![]),
+            };
+            let info = RoomBaseInfo::request(http, room_id).await?;
+            RoomInfo::write_activity(
+                room_id,
+                RoomActivity {
+                    title: info.title.clone(),
+                    area: info.area_name.clone(),
+                    parent_area: info.parent_area_name.clone(),
+                },
+            );
+            let live_time = NaiveDateTime::parse_from_str(&info.live_time, "%Y-%m-%d %H:%M:%S")
+                .ok()
+                .and_then(|t| Local.from_local_datetime(&t).single());
+            Ok(vec![SpiderData::RoomInfo(RoomStat {
+                title: info.title,
+                area_id: info.area_id,
+                area: info.area_name,
+                parent_area_id: info.parent_area_id,
+                parent_area: info.parent_area_name,
+                cover: info.user_cover,
+                live_status: info.live_status,
+                live_time,
+            })])
+        }
+        .boxed()
+    }
+}
@@ -0,0 +1,25 @@
+use anyhow::Result;
+use biliapi::{requests::UploaderStat, Request};
+use futures::future::{BoxFuture, FutureExt};
+
+use super::{SpiderData, SpiderJob};
+use crate::http::BiliHttp;
+
+/// 视频、专栏总播放和点赞
+pub struct UploaderStatJob;
+
+impl SpiderJob for UploaderStatJob {
+    fn name(&self) -> &'static str {
+        "uploader_stat"
+    }
+
+    fn fetch<'a>(&'a self, http: &'a BiliHttp, uid: u64) -> BoxFuture<'a, Result<Vec<SpiderData>>> {
+        async move {
+            let stat = http
+                .request(|client| async move { UploaderStat::request(&client, uid).await })
+                .await?;
+            Ok(vec![SpiderData::UploaderStat(stat)])
+        }
+        .boxed()
+    }
+}
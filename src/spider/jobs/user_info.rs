@@ -0,0 +1,25 @@
+use anyhow::Result;
+use biliapi::{requests::UserInfo, Request};
+use futures::future::{BoxFuture, FutureExt};
+
+use super::{SpiderData, SpiderJob};
+use crate::http::BiliHttp;
+
+/// 粉丝数
+pub struct UserInfoJob;
+
+impl SpiderJob for UserInfoJob {
+    fn name(&self) -> &'static str {
+        "user_info"
+    }
+
+    fn fetch<'a>(&'a self, http: &'a BiliHttp, uid: u64) -> BoxFuture<'a, Result<Vec<SpiderData>>> {
+        async move {
+            let user_info = http
+                .request(|client| async move { UserInfo::request(&client, uid).await })
+                .await?;
+            Ok(vec![SpiderData::UserInfo(user_info)])
+        }
+        .boxed()
+    }
+}
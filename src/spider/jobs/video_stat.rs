@@ -0,0 +1,65 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::{TimeZone, Utc};
+use futures::future::{BoxFuture, FutureExt};
+
+use super::{JobSchedule, SpiderData, SpiderJob};
+use crate::{
+    http::BiliHttp,
+    requests::{ArchiveStat, SpaceVideos},
+    spider::VideoStat,
+};
+
+/// 每次看最近多少个视频
+const RECENT_VIDEOS: u32 = 10;
+/// 只统计发布多久以内的视频
+const MAX_AGE: Duration = Duration::from_secs(30 * 24 * 3600);
+/// 每个视频之间的间隔
+const VIDEO_INTERVAL: Duration = Duration::from_millis(500);
+
+/// 最近视频的播放、点赞、投币、收藏、分享和弹幕数
+pub struct VideoStatJob;
+
+impl SpiderJob for VideoStatJob {
+    fn name(&self) -> &'static str {
+        "video_stat"
+    }
+
+    fn default_schedule(&self) -> JobSchedule {
+        JobSchedule {
+            enabled: true,
+            interval: 15 * 60,
+            pacing_ms: 2_000,
+        }
+    }
+
+    fn fetch<'a>(&'a self, http: &'a BiliHttp, uid: u64) -> BoxFuture<'a, Result<Vec<SpiderData>>> {
+        async move {
+            let videos = SpaceVideos::request(http, uid, RECENT_VIDEOS).await?;
+            let oldest = Utc::now().timestamp() - MAX_AGE.as_secs() as i64;
+            let mut data = vec![];
+            for video in videos.list.vlist {
+                if video.created < oldest {
+                    continue;
+                }
+                tokio::time::sleep(VIDEO_INTERVAL).await;
+                let stat = match ArchiveStat::request(http, &video.bvid).await {
+                    Ok(stat) => stat,
+                    Err(e) => {
+                        warn!("failed to get stat of video {}: {:?}", video.bvid, e);
+                        continue;
+                    }
+                };
+                data.push(SpiderData::VideoStat(VideoStat {
+                    bvid: video.bvid,
+                    title: video.title,
+                    created: Utc.timestamp(video.created, 0),
+                    stat,
+                }));
+            }
+            Ok(data)
+        }
+        .boxed()
+    }
+}
@@ -0,0 +1,339 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::*;
+use biliapi::requests::{UploaderStat, UserInfo};
+use chrono::{DateTime, Local, Utc};
+use cookie_store::CookieStore;
+use influxdb_client::Point;
+use parking_lot::Mutex;
+use reqwest_cookie_store::CookieStoreMutex;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{broadcast, oneshot, watch};
+
+use crate::{
+    http::{default_builder, BiliHttp},
+    influx::{
+        config::{measurement, new_point},
+        RoomInfo,
+    },
+    requests::ArchiveStat,
+    task_factory::TaskSet,
+};
+
+pub mod cookies;
+pub mod jobs;
+use cookies::CookieKeeper;
+use jobs::{JobSchedule, JobScheduleConfig, SpiderJob};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SpiderInfo {
+    /// 由 user_info 任务得到，还没有时为 None
+    #[serde(default)]
+    pub username: Option<String>,
+    pub uid: u64,
+    /// 用户是某个监控中的直播间的主播时，为该直播间
+    #[serde(default)]
+    pub room_id: Option<u64>,
+    pub time: DateTime<Utc>,
+    pub data: SpiderData,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum SpiderData {
+    UploaderStat(UploaderStat),
+    UserInfo(UserInfo),
+    VideoStat(VideoStat),
+    Guard(GuardStat),
+    RoomInfo(RoomStat),
+}
+
+/// 单个视频的统计
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct VideoStat {
+    pub bvid: String,
+    pub title: String,
+    /// 发布时间
+    pub created: DateTime<Utc>,
+    pub stat: ArchiveStat,
+}
+
+/// 直播间的大航海和粉丝团人数
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct GuardStat {
+    /// 接口返回的总数
+    pub total: u64,
+    /// 总督
+    pub governors: u64,
+    /// 提督
+    pub admirals: u64,
+    /// 舰长
+    pub captains: u64,
+    /// 粉丝团人数
+    pub fans_club: u64,
+}
+
+/// 直播间的标题、分区等
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RoomStat {
+    pub title: String,
+    pub area_id: u64,
+    pub area: String,
+    pub parent_area_id: u64,
+    pub parent_area: String,
+    pub cover: String,
+    /// 0 未开播，1 直播中，2 轮播
+    pub live_status: u8,
+    /// 开播时间，未开播时为 None
+    pub live_time: Option<DateTime<Local>>,
+}
+
+impl SpiderInfo {
+    pub fn into_point(self) -> Point {
+        let measurement = match self.data {
+            SpiderData::VideoStat(_) => measurement::BILI_VIDEO,
+            SpiderData::Guard(_) => measurement::GUARD,
+            SpiderData::RoomInfo(_) => measurement::ROOM,
+            _ => measurement::BILI_INFO,
+        };
+        // 用户名没拿到之前只有 uid，不用别的名字代替，免得同一个用户分成几个 series
+        let mut pt = new_point(measurement)
+            .tag("uid", self.uid.to_string())
+            .timestamp(self.time.timestamp_millis());
+        if let Some(username) = self.username {
+            pt = pt.tag("uploader", username);
+        }
+        // live-guard、live-room 和其他 live- 一样带上直播间的 tag
+        let room_info = match (&self.data, self.room_id) {
+            (SpiderData::Guard(_) | SpiderData::RoomInfo(_), Some(room_id)) => {
+                RoomInfo::from_cache_opt(room_id)
+            }
+            _ => None,
+        };
+        if let Some(room_info) = room_info {
+            pt = room_info.tag_point(pt);
+        } else if let Some(room_id) = self.room_id {
+            pt = pt.tag("room_id", room_id.to_string());
+        }
+        match self.data {
+            SpiderData::UploaderStat(stat) => pt
+                .field("video_views", stat.video_views as f64)
+                .field("article_views", stat.article_views as f64)
+                .field("likes", stat.likes as f64),
+            SpiderData::UserInfo(user_info) => pt.field("followers", user_info.followers as f64),
+            SpiderData::VideoStat(video) => pt
+                .tag("bvid", video.bvid)
+                // 标题会改，写成 tag 会多出 series
+                .field("title", video.title)
+                .field("age_hours", (self.time - video.created).num_hours() as f64)
+                .field("views", video.stat.view as f64)
+                .field("likes", video.stat.like as f64)
+                .field("coins", video.stat.coin as f64)
+                .field("favorites", video.stat.favorite as f64)
+                .field("shares", video.stat.share as f64)
+                .field("danmaku", video.stat.danmaku as f64)
+                .field("replies", video.stat.reply as f64),
+            SpiderData::Guard(guard) => pt
+                .field("total", guard.total as f64)
+                .field("governors", guard.governors as f64)
+                .field("admirals", guard.admirals as f64)
+                .field("captains", guard.captains as f64)
+                .field("fans_club", guard.fans_club as f64),
+            SpiderData::RoomInfo(room) => pt
+                .field("title", room.title)
+                .tag("area", room.area)
+                .tag("parent_area", room.parent_area)
+                .field("area_id", room.area_id as i64)
+                .field("parent_area_id", room.parent_area_id as i64)
+                .field("cover", room.cover)
+                .field("live_status", room.live_status as i64)
+                .field(
+                    "live_time",
+                    room.live_time.map(|t| t.timestamp()).unwrap_or(0),
+                ),
+        }
+    }
+}
+
+/// 各个任务的调度，key 为任务名
+pub type SpiderSchedules = HashMap<String, JobScheduleConfig>;
+
+pub struct Spider {
+    tasks: watch::Receiver<TaskSet>,
+    schedules: watch::Receiver<SpiderSchedules>,
+    publish: broadcast::Sender<SpiderInfo>,
+    http: BiliHttp,
+    /// 变化时立刻开始一轮，任务正在跑时会在跑完后再跑一轮
+    trigger: watch::Receiver<u64>,
+    /// uid => 用户名
+    usernames: Arc<Mutex<HashMap<u64, String>>>,
+    cookies: Arc<CookieStoreMutex>,
+    cookie_keeper: Option<CookieKeeper>,
+}
+
+impl Spider {
+    pub fn new(
+        tasks: watch::Receiver<TaskSet>,
+        schedules: watch::Receiver<SpiderSchedules>,
+        publish: broadcast::Sender<SpiderInfo>,
+        http: &BiliHttp,
+        path: impl AsRef<Path>,
+        trigger: watch::Receiver<u64>,
+    ) -> Self {
+        // load client
+        let path = path.as_ref().to_path_buf();
+        let (http, cookies, refresh_token) = persisted_client(http, &path).unwrap();
+        let cookie_keeper = CookieKeeper::new(path, http.clone(), cookies.clone(), refresh_token);
+        Self {
+            tasks,
+            schedules,
+            publish,
+            http,
+            trigger,
+            usernames: Default::default(),
+            cookies,
+            cookie_keeper: Some(cookie_keeper),
+        }
+    }
+
+    /// 带 cookie 的客户端
+    pub fn http(&self) -> BiliHttp {
+        self.http.clone()
+    }
+
+    pub fn cookies(&self) -> Arc<CookieStoreMutex> {
+        self.cookies.clone()
+    }
+
+    pub async fn start(mut self, stop: oneshot::Receiver<()>) -> Result<()> {
+        let cookie_keeper = self.cookie_keeper.take().expect("spider started twice");
+        let spider = Arc::new(self);
+        let runners = jobs::all_jobs()
+            .into_iter()
+            .map(|job| spider.clone().run_job(job));
+        tokio::select! {
+            _ = stop => {
+                debug!("spider stopped");
+                Ok(())
+            },
+            ret = futures::future::try_join_all(runners) => {
+                error!("spider stopped unexpectedly: {:?}", ret);
+                ret.map(|_| ())
+            }
+            ret = cookie_keeper.run() => {
+                error!("cookie keeper stopped unexpectedly: {:?}", ret);
+                ret
+            }
+        }
+    }
+
+    /// 按调度一直执行一个任务
+    async fn run_job(self: Arc<Self>, job: Arc<dyn SpiderJob>) -> Result<()> {
+        let mut schedules = self.schedules.clone();
+        let mut trigger = self.trigger.clone();
+
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        info!("bilibili info spider job {} started.", job.name());
+        let mut schedule = Self::schedule(&schedules, job.as_ref());
+        loop {
+            if schedule.enabled {
+                self.run_batch(job.as_ref(), &schedule).await;
+            } else {
+                debug!("spider job {} disabled.", job.name());
+            }
+
+            // 调度改变时只更新间隔，从上一轮结束开始算
+            let last_run = tokio::time::Instant::now();
+            loop {
+                let next_run = last_run + Duration::from_secs(schedule.interval);
+                tokio::select! {
+                    _ = tokio::time::sleep_until(next_run) => break,
+                    ret = trigger.changed() => {
+                        ret?;
+                        info!("spider job {} triggered manually.", job.name());
+                        break;
+                    }
+                    ret = schedules.changed() => {
+                        ret?;
+                        debug!("spider schedules changed.");
+                        let was_enabled = schedule.enabled;
+                        schedule = Self::schedule(&schedules, job.as_ref());
+                        // 重新启用的任务立刻开始
+                        if schedule.enabled && !was_enabled {
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn schedule(schedules: &watch::Receiver<SpiderSchedules>, job: &dyn SpiderJob) -> JobSchedule {
+        match schedules.borrow().get(job.name()) {
+            Some(config) => config.resolve(job.default_schedule()),
+            None => job.default_schedule(),
+        }
+    }
+
+    async fn run_batch(&self, job: &dyn SpiderJob, schedule: &JobSchedule) {
+        let mut tasks = self.tasks.borrow().clone();
+        tasks.extend(RoomInfo::spider_uploaders());
+        info!("spider job {}: fetch {} users...", job.name(), tasks.len());
+        debug!("current batch tasks = {:?}", tasks);
+        let mut user_cd = tokio::time::interval(Duration::from_millis(schedule.pacing_ms.max(1)));
+        user_cd.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        for uid in tasks {
+            user_cd.tick().await;
+            if let Err(e) = self.process(job, uid).await {
+                warn!("spider job {} failed for user {}: {:?}", job.name(), uid, e);
+            }
+        }
+    }
+
+    async fn process(&self, job: &dyn SpiderJob, uid: u64) -> Result<()> {
+        debug!("spider job {} for id={}", job.name(), uid);
+        let data = job.fetch(&self.http, uid).await?;
+        let room_id = RoomInfo::from_anchor_uid(uid).map(|room| room.id);
+        for data in data {
+            if let SpiderData::UserInfo(user_info) = &data {
+                self.usernames.lock().insert(uid, user_info.name.clone());
+            }
+            let username = self.username(uid);
+            self.publish.send(SpiderInfo {
+                username,
+                uid,
+                room_id,
+                time: Utc::now(),
+                data,
+            })?;
+        }
+        Ok(())
+    }
+
+    /// 用户名，由 user_info 任务得到。不在这里单独请求，以免绕过各个任务的调度
+    fn username(&self, uid: u64) -> Option<String> {
+        self.usernames.lock().get(&uid).cloned()
+    }
+}
+
+/// 从 cookie 文件获取持久化的客户端
+fn persisted_client(
+    http: &BiliHttp,
+    cookie_json: impl AsRef<Path>,
+) -> Result<(BiliHttp, Arc<CookieStoreMutex>, Option<String>)> {
+    let (cookies, refresh_token) = cookies::load(cookie_json).unwrap_or_else(|e| {
+        warn!(
+            "failed to load cookies, spider will run unauthenticated: {:?}",
+            e
+        );
+        (CookieStore::default(), None)
+    });
+
+    let cookies = Arc::new(CookieStoreMutex::new(cookies));
+
+    let http = http.with_clients(|| default_builder().cookie_provider(Arc::clone(&cookies)))?;
+
+    Ok((http, cookies, refresh_token))
+}
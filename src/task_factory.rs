@@ -1,42 +1,218 @@
-use serde::Deserialize;
-use std::{collections::HashSet, path::PathBuf, time::Duration};
+use serde::{Deserialize, Serialize, Serializer};
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
 use anyhow::{Context, Result};
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, oneshot};
 
+use crate::{
+    alert::AlertRule, discovery::DiscoveryConfig, keyword::KeywordRule,
+    live_status::LiveOnlyConfig, spider::jobs::JobScheduleConfig,
+};
+
+/// 无法监听文件变化时的轮询间隔
 const REFRESH_DURATION: Duration = Duration::from_secs(10);
+/// 合并短时间内的多次文件变化
+const DEBOUNCE_DURATION: Duration = Duration::from_millis(500);
 
 pub type TaskSet = HashSet<u64>;
 
-#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
 pub struct Config {
+    #[serde(default, serialize_with = "serialize_sorted")]
     pub live_rooms: TaskSet,
+    #[serde(default, serialize_with = "serialize_sorted")]
     pub users: TaskSet,
+    /// 带设置的直播间，写作 `[[room]]`，不需要再写进 live_rooms
+    #[serde(default, rename = "room", skip_serializing_if = "Vec::is_empty")]
+    pub rooms: Vec<RoomConfig>,
+    /// 按分区自动发现直播间
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub discovery: Option<DiscoveryConfig>,
+    /// 只在开播时连接
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub live_only: Option<LiveOnlyConfig>,
+    /// 各个爬虫任务的调度，写作 `[spider.<任务名>]`
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub spider: HashMap<String, JobScheduleConfig>,
+    /// webhook 提醒，写作 `[[alert]]`
+    #[serde(default, rename = "alert", skip_serializing_if = "Vec::is_empty")]
+    pub alerts: Vec<AlertRule>,
+    /// 弹幕关键词，写作 `[[keyword]]`
+    #[serde(default, rename = "keyword", skip_serializing_if = "Vec::is_empty")]
+    pub keywords: Vec<KeywordRule>,
+}
+
+impl Config {
+    /// 所有需要监控的直播间
+    pub fn all_rooms(&self) -> TaskSet {
+        let mut rooms = self.live_rooms.clone();
+        rooms.extend(self.rooms.iter().map(|room| room.id));
+        rooms
+    }
+
+    pub fn contains_room(&self, id: u64) -> bool {
+        self.live_rooms.contains(&id) || self.rooms.iter().any(|room| room.id == id)
+    }
+
+    /// 直播间的设置，没有单独设置时为默认值
+    pub fn room_options(&self, id: u64) -> RoomOptions {
+        self.rooms
+            .iter()
+            .find(|room| room.id == id)
+            .map(|room| room.options.clone())
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub struct RoomConfig {
+    pub id: u64,
+    #[serde(flatten)]
+    pub options: RoomOptions,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub struct RoomOptions {
+    /// 显示名，作为 influx 的 alias tag
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alias: Option<String>,
+    /// 分组（如 ASOUL），作为 influx 的 group tag
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    /// 是否录制到原始文件
+    #[serde(default = "default_true")]
+    pub record_raw: bool,
+    /// 原始文件里是否保存弹幕
+    #[serde(default = "default_true")]
+    pub store_danmu: bool,
+    /// 是否同时爬主播的 up 主信息，不需要再写进 users
+    #[serde(default = "default_true")]
+    pub spider_uploader: bool,
+}
+
+impl Default for RoomOptions {
+    fn default() -> Self {
+        Self {
+            alias: None,
+            group: None,
+            record_raw: true,
+            store_danmu: true,
+            spider_uploader: true,
+        }
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// 写回文件时保持有序，方便人看
+fn serialize_sorted<S: Serializer>(set: &TaskSet, serializer: S) -> Result<S::Ok, S::Error> {
+    let mut v: Vec<_> = set.iter().collect();
+    v.sort_unstable();
+    v.serialize(serializer)
+}
+
+/// 运行时对任务的修改，会写回任务文件
+#[derive(Debug, Clone, Copy)]
+pub enum TaskChange {
+    AddRoom(u64),
+    RemoveRoom(u64),
+    AddUser(u64),
+    RemoveUser(u64),
+}
+
+impl TaskChange {
+    /// 返回 config 是否有变化
+    fn apply(self, config: &mut Config) -> bool {
+        match self {
+            TaskChange::AddRoom(id) => {
+                !config.rooms.iter().any(|room| room.id == id) && config.live_rooms.insert(id)
+            }
+            TaskChange::RemoveRoom(id) => {
+                let len = config.rooms.len();
+                config.rooms.retain(|room| room.id != id);
+                config.live_rooms.remove(&id) || config.rooms.len() != len
+            }
+            TaskChange::AddUser(id) => config.users.insert(id),
+            TaskChange::RemoveUser(id) => config.users.remove(&id),
+        }
+    }
+}
+
+pub type TaskCommand = (TaskChange, oneshot::Sender<Result<()>>);
+
+#[derive(Debug)]
+enum ReloadReason {
+    FileChanged,
+    Hangup,
+    Poll,
 }
 
 pub struct TaskFactory {
     task_file: PathBuf,
     sender: mpsc::Sender<Config>,
+    commands: mpsc::Receiver<TaskCommand>,
+    /// 最后一次有效的任务
     last: Option<Config>,
+    /// 最后一次解析失败的错误，用来避免重复报告
+    last_error: Option<String>,
 }
 
 impl TaskFactory {
-    pub fn start(task_file: PathBuf) -> mpsc::Receiver<Config> {
+    pub fn start(task_file: PathBuf) -> (mpsc::Receiver<Config>, mpsc::Sender<TaskCommand>) {
         let (sender, receiver) = mpsc::channel(5);
+        let (command_sender, commands) = mpsc::channel(5);
         let factory = Self {
             task_file,
             sender,
+            commands,
             last: None,
+            last_error: None,
         };
         tokio::spawn(factory.run());
-        receiver
+        (receiver, command_sender)
     }
 
-    /// 一直循环解析任务，或等到特定的信号结束
+    /// 文件变化、SIGHUP 时重新解析任务，或等到特定的信号结束
     async fn run(mut self) -> Result<()> {
+        let mut reloads = self.reload_events();
+        self.reload().await?;
         loop {
-            match self.load_tasks().await {
-                Ok(tasks) => match self.last.as_ref() {
+            tokio::select! {
+                _ = Self::stop_signal() => {
+                    std::mem::drop(self.sender);
+                    info!("ctrl+c received, stopping task_factory.");
+                    return Ok(());
+                }
+                Some(reason) = reloads.recv() => {
+                    // 编辑器保存时往往会连着触发好几个事件（写临时文件再 rename），合并成一次
+                    tokio::time::sleep(DEBOUNCE_DURATION).await;
+                    while reloads.try_recv().is_ok() {}
+                    debug!("reloading tasks: {:?}", reason);
+                    self.reload().await?;
+                }
+                Some((change, reply)) = self.commands.recv() => {
+                    let ret = self.on_change(change).await;
+                    if let Err(e) = &ret {
+                        warn!("failed to apply task change {:?}: {:?}", change, e);
+                    }
+                    let _ = reply.send(ret);
+                }
+            }
+        }
+    }
+
+    /// 重新读取任务文件，失败时保留上一次的任务
+    async fn reload(&mut self) -> Result<()> {
+        match self.load_tasks().await {
+            Ok(tasks) => {
+                self.last_error = None;
+                match self.last.as_ref() {
                     None => {
                         info!("task initialized => tasks = {:?}", tasks);
                         self.last = Some(tasks.clone());
@@ -50,23 +226,138 @@ impl TaskFactory {
                     Some(_last) => {
                         debug!("task not changed.");
                     }
-                },
-                Err(e) => {
+                }
+            }
+            Err(e) => {
+                // 同一个错误只报告一次
+                let msg = format!("{:?}", e);
+                if self.last_error.as_ref() != Some(&msg) {
                     warn!(
-                        "task factory failed to load tasks: {:?}. Will try again.",
-                        e
+                        "task factory failed to load tasks: {}. Keep using the last valid tasks.",
+                        msg
                     );
+                    self.last_error = Some(msg);
                 }
             }
-            tokio::select! {
-                _ = Self::stop_signal() => {
-                    std::mem::drop(self.sender);
-                    info!("ctrl+c received, stopping task_factory.");
-                    return Ok(());
+        }
+        Ok(())
+    }
+
+    /// 监听任务文件的变化和 SIGHUP，无法监听文件时退化为轮询
+    fn reload_events(&self) -> mpsc::Receiver<ReloadReason> {
+        let (tx, rx) = mpsc::channel(16);
+
+        if let Err(e) = Self::watch_file(&self.task_file, tx.clone()) {
+            warn!(
+                "failed to watch task file: {:?}. Fallback to polling every {:?}.",
+                e, REFRESH_DURATION
+            );
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                loop {
+                    tokio::time::sleep(REFRESH_DURATION).await;
+                    if tx.send(ReloadReason::Poll).await.is_err() {
+                        return;
+                    }
+                }
+            });
+        }
+
+        #[cfg(unix)]
+        {
+            use tokio::signal::unix::{signal, SignalKind};
+            match signal(SignalKind::hangup()) {
+                Ok(mut hangup) => {
+                    tokio::spawn(async move {
+                        while hangup.recv().await.is_some() {
+                            info!("SIGHUP received, reloading tasks.");
+                            if tx.send(ReloadReason::Hangup).await.is_err() {
+                                return;
+                            }
+                        }
+                    });
+                }
+                Err(e) => warn!("failed to listen to SIGHUP: {:?}", e),
+            }
+        }
+
+        rx
+    }
+
+    /// 监听所在的目录而不是文件本身，这样 rename 过来的新文件也能收到
+    fn watch_file(task_file: &Path, tx: mpsc::Sender<ReloadReason>) -> Result<()> {
+        use notify::{DebouncedEvent, RecursiveMode, Watcher};
+
+        let task_file = task_file.canonicalize()?;
+        let dir = task_file
+            .parent()
+            .context("task file has no parent directory")?
+            .to_path_buf();
+
+        let (notify_tx, notify_rx) = std::sync::mpsc::channel();
+        let mut watcher = notify::watcher(notify_tx, DEBOUNCE_DURATION)?;
+        watcher.watch(&dir, RecursiveMode::NonRecursive)?;
+        info!("watching task file {:?}", task_file);
+
+        std::thread::spawn(move || {
+            // watcher 需要一直活着
+            let _watcher = watcher;
+            for event in notify_rx {
+                let path = match &event {
+                    DebouncedEvent::Create(path)
+                    | DebouncedEvent::Write(path)
+                    | DebouncedEvent::Remove(path)
+                    | DebouncedEvent::Rename(_, path) => path,
+                    DebouncedEvent::Rescan => &task_file,
+                    DebouncedEvent::Error(e, path) => {
+                        warn!("task file watcher error: {:?} (path = {:?})", e, path);
+                        continue;
+                    }
+                    _ => continue,
+                };
+                if path != &task_file {
+                    continue;
+                }
+                if tx.blocking_send(ReloadReason::FileChanged).is_err() {
+                    return;
                 }
-                _ = tokio::time::sleep(REFRESH_DURATION) => {}
             }
+            warn!("task file watcher stopped.");
+        });
+        Ok(())
+    }
+
+    /// 在最新的文件内容上应用修改并写回，这样不会覆盖掉手动的修改
+    ///
+    /// 文件无效时不修改，也不用上一次的有效配置覆盖文件，错误返回给调用者
+    async fn on_change(&mut self, change: TaskChange) -> Result<()> {
+        let mut config = self
+            .load_tasks()
+            .await
+            .context("task file is invalid, fix it before changing tasks")?;
+        if !change.apply(&mut config) {
+            debug!("task change {:?} has no effect.", change);
+            return Ok(());
+        }
+        self.save_tasks(&config).await?;
+        info!("task changed by {:?} => tasks = {:?}", change, config);
+        if self.last.as_ref() != Some(&config) {
+            self.last = Some(config.clone());
+            self.sender.send(config).await?;
         }
+        Ok(())
+    }
+
+    /// 先写临时文件再 rename，避免写到一半被读到
+    ///
+    /// 整个文件按 Config 重新生成，原来的注释和排版不会保留
+    async fn save_tasks(&self, config: &Config) -> Result<()> {
+        let content = toml::to_string(config)?;
+        let mut tmp = self.task_file.clone().into_os_string();
+        tmp.push(".tmp");
+        tokio::fs::write(&tmp, content).await?;
+        tokio::fs::rename(&tmp, &self.task_file).await?;
+        Ok(())
     }
 
     #[cfg(not(unix))]
@@ -115,3 +406,65 @@ impl TaskFactory {
         Ok(tasks)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn config_round_trips() {
+        // admin 接口写回 watch.toml 时每一节都要能重新读出来
+        let content = r#"
+live_rooms = [22637261, 92613]
+users = [672328094]
+
+[[room]]
+id = 21452505
+alias = "海子姐"
+group = "VR"
+spider_uploader = false
+
+[discovery]
+interval = 600
+
+[[discovery.area]]
+parent_area_id = 9
+max_rank = 30
+
+[live_only]
+poll_interval = 30
+
+[spider.video_stat]
+interval = 3600
+
+[spider.user_info]
+enabled = false
+
+[[alert]]
+kind = "super_chat"
+min_price = 100
+webhook = "https://example.com/hook"
+body = '{"text": "{streamer} 收到了 {user} 的 ￥{price} SC：{message}"}'
+rooms = [22637261]
+
+[[alert]]
+kind = "keyword"
+webhook = "https://example.com/hook"
+body = '{"text": "{keyword}"}'
+
+[[keyword]]
+name = "问号"
+pattern = "^[?？]+$"
+regex = true
+"#;
+        let config: Config = toml::from_str(content).unwrap();
+        assert_eq!(config.rooms.len(), 1);
+        assert_eq!(config.spider.len(), 2);
+        assert_eq!(config.alerts.len(), 2);
+        assert_eq!(config.keywords.len(), 1);
+
+        let saved = toml::to_string(&config).unwrap();
+        let reloaded: Config = toml::from_str(&saved).unwrap();
+        assert_eq!(reloaded, config);
+    }
+}
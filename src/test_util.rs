@@ -0,0 +1,25 @@
+//! 测试共用的 packet
+
+use biliapi::ws_protocol::Packet;
+use chrono::Local;
+use serde_json::Value;
+
+/// 和录制文件里的格式一样的 packet
+pub fn packet(room_id: u64, msg: Value) -> Packet {
+    serde_json::from_value(serde_json::json!({
+        "operation": "SendMsgReply",
+        "body": msg.to_string(),
+        "time": Local::now(),
+        "room_id": room_id,
+    }))
+    .unwrap()
+}
+
+/// fixture 里的 SC，换成给定的 id 和价格（元）
+pub fn super_chat(room_id: u64, id: u64, price: u32) -> Packet {
+    let mut msg: Value =
+        serde_json::from_str(include_str!("../fixtures/messages/super_chat_message.json")).unwrap();
+    msg["data"]["id"] = id.into();
+    msg["data"]["price"] = price.into();
+    packet(room_id, msg)
+}